@@ -70,4 +70,35 @@ x = key, y = value
 l = length of key in bytes, m = length of value in bytes
 pattern repeats
 remainder of space is padded with random bytes
+
+Version 1 extends the header with an algorithm byte and a flags byte, so the
+record layout no longer implies how it is obfuscated:
+
+4d 43 4f 4e 46 01 aa ff
+ll xx xx xx xx xx ... mm yy yy yy yy yy ...
+
+a = algorithm tag (0 = none, 1 = XOR against the secret, 2 = XOR of the value bytes only,
+    3 = ChaCha20-Poly1305 encryption of the whole entries region)
+f = flags, each set bit adds an optional field after the flags byte, in bit order:
+    0x01 = 16-byte fingerprint: an 8-byte random salt, then the first 8 bytes of an
+           HMAC-SHA256 keyed with the obfuscation key over a label and the salt, checked
+           before the entries are deobfuscated
+    0x02 = PBKDF2 parameters: 4-byte little-endian iteration count and 16-byte salt; the
+           obfuscation key is then derived from the secret instead of being the secret itself.
+           Always set when the algorithm is 3
+    0x04 = 4-byte little-endian block size, when it is not the default 8,192 bytes
+    0x08 = 8-byte little-endian Unix timestamp after which the secret should be rotated
+    0x10 = 12-byte nonce and 16-byte authentication tag; set exactly when the algorithm is 3.
+           The rest of the header, with the tag zeroed, is authenticated along with the entries
+    0x20 = no field; the XORed entries start with a 4-byte sentinel derived from the key, so a
+           wrong secret is detected after deobfuscation. Set exactly when the algorithm is 1
+    0x40 = 4-byte little-endian CRC32 of the records before obfuscation, up to and including the
+           terminator, checked after deobfuscation
+
+Version 2 has the same header as version 1, but every key and value length, and the
+terminator, takes two bytes in little-endian order, so keys and values can be up to
+65,535 bytes long within the block size:
+
+4d 43 4f 4e 46 02 aa ff
+ll ll xx xx xx xx xx ... mm mm yy yy yy yy yy ...
  */
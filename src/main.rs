@@ -1,9 +1,9 @@
 use clap::{Arg, ArgAction};
+use mconfig::mconfigurator::{MCError, MConfigFile};
 use mconfig::MConfig;
 use std::error::Error;
-use std::fs::{write, read};
-use std::io::Write;
-use std::path::{PathBuf};
+use std::io::{ErrorKind, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let arg_matches = clap::command!()
@@ -55,69 +55,164 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .action(ArgAction::SetTrue)
                 .help("Delete the specified key and value, if any."),
         )
+        .arg(
+            Arg::new("batch")
+                .long("batch")
+                .short('b')
+                .conflicts_with_all(["list", "key"])
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Apply the operations listed in a file, one per line: 'set KEY VALUE', 'empty KEY' or 'remove KEY'. Nothing is written unless all of them succeed."),
+        )
+        .arg(
+            Arg::new("import")
+                .long("import")
+                .short('i')
+                .conflicts_with_all(["key", "batch"])
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Import the keys and values of a flat JSON object (.json, null for no value) or a .env file (KEY=VALUE, or KEY alone for no value). A repeated key keeps its last value. Nothing is written unless all of them fit."),
+        )
+        .arg(
+            Arg::new("export")
+                .long("export")
+                .short('x')
+                .conflicts_with("key")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Write all keys and values to a JSON file, with null for keys without a value."),
+        )
+        .arg(
+            Arg::new("create")
+                .long("create")
+                .short('c')
+                .action(ArgAction::SetTrue)
+                .help("Create a new empty file with the entered secret instead of opening an existing one."),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .requires("create")
+                .action(ArgAction::SetTrue)
+                .help("Allow --create to overwrite an existing file."),
+        )
         .get_matches();
 
     let file = arg_matches
         .get_one::<PathBuf>("file")
         .expect("Required parameter 'file' is missing.");
-    let data = match read(file) {
-        Ok(d) => {
-            println!("Loaded {} bytes from {}", d.len(), file.display());
-            d
-        }
-        Err(e) => {
-            eprintln!("Error loading {}: {}", file.display(), e);
-            return Err(e.into());
-        }
+    let create = arg_matches.get_flag("create");
+    if create && file.exists() && !arg_matches.get_flag("force") {
+        eprintln!("{} already exists, use --force to overwrite it.", file.display());
+        return Err(MCError::Io(ErrorKind::AlreadyExists).into());
+    }
+
+    // an existing file is opened before prompting, so that a missing or locked file fails early;
+    // it is only opened for writing if something will be written, so read-only files can be listed
+    let writes = ["batch", "import"].iter().any(|a| arg_matches.contains_id(a))
+        || ["remove", "empty"].iter().any(|a| arg_matches.get_flag(a))
+        || arg_matches.contains_id("value");
+    let opened = match (create, writes) {
+        (true, _) => None,
+        (false, true) => Some(open_file(file, MConfigFile::open(file))?),
+        (false, false) => Some(open_file(file, MConfigFile::open_read_only(file))?),
     };
 
-    // Retrieve secret from stdin. Todo: make this hide the typed text visually
+    // Retrieve secret from stdin, without echoing it when typed at a terminal
     print!("Enter secret: ");
     std::io::stdout().flush()?;
-    let mut secret = String::new();
-    std::io::stdin().read_line(&mut secret)?;
-
-    let mut mcnf = match MConfig::builder()
-        .load(data)
-        .secret(&secret.trim())
-        .try_build()
-    {
-        Ok(m) => {
-            println!("Loaded MConfigurator data with {} entries.", m.len());
-            m
-        }
-        Err(e) => {
-            eprintln!("Failed to load MConfigurator data: {}", e);
-            return Err(e.into());
-        }
+    let secret = if std::io::stdin().is_terminal() {
+        rpassword::read_password()?
+    } else {
+        let mut secret = String::new();
+        std::io::stdin().read_line(&mut secret)?;
+        secret
+    };
+    // an empty line means the file has no secret
+    let secret = secret.trim();
+    let secret = (!secret.is_empty()).then_some(secret);
+
+    let (mut data, mut mcnf) = match opened {
+        Some(mut data) => match data.load(secret) {
+            Ok(m) => {
+                println!("Loaded MConfigurator data with {} entries.", m.len());
+                (data, m)
+            }
+            Err(e) => {
+                eprintln!("Failed to load MConfigurator data: {}", e);
+                return Err(e.into());
+            }
+        },
+        None => create_file(file, secret)?,
     };
 
     // listing objects, nothing else
     if arg_matches.get_flag("list") {
-        for (k, v) in mcnf.iter() {
-            let v = v.clone().unwrap_or("<empty>".to_string());
+        for k in mcnf.sorted_keys() {
+            let v = mcnf[k].clone().unwrap_or("<empty>".to_string());
             println!("{k}: {v}");
         }
     }
 
+    // batch edits are applied in memory and written once, only if all of them succeed
+    if let Some(batch) = arg_matches.get_one::<PathBuf>("batch") {
+        let ops = std::fs::read_to_string(batch)?;
+        match apply_batch(&mut mcnf, &ops) {
+            Ok(count) => {
+                data.store(&mcnf)?;
+                println!("Applied {count} operations from {}", batch.display());
+                println!("Updated {}", file.display());
+            }
+            Err(e) => {
+                eprintln!("Batch not applied, {} is unchanged: {}", file.display(), e);
+                return Err(e);
+            }
+        }
+    }
+
+    // imported entries are inserted in memory and written once, only if all of them fit
+    if let Some(import) = arg_matches.get_one::<PathBuf>("import") {
+        let result = read_import(import).and_then(|entries| {
+            let count = entries.len();
+            for (key, value) in entries {
+                mcnf.try_insert(key.clone(), value).map_err(|e| format!("{key}: {e}"))?;
+            }
+            Ok(count)
+        });
+        match result {
+            Ok(count) => {
+                data.store(&mcnf)?;
+                println!("Imported {count} entries from {}", import.display());
+                println!("Updated {}", file.display());
+            }
+            Err(e) => {
+                eprintln!("Import failed, {} is unchanged: {}", file.display(), e);
+                return Err(e);
+            }
+        }
+    }
+
+    // exporting happens after any batch or import, and writes the decrypted entries as a JSON object, sorted by key
+    if let Some(export) = arg_matches.get_one::<PathBuf>("export") {
+        std::fs::write(export, mcnf.to_json())?;
+        println!("Exported {} entries to {}", mcnf.len(), export.display());
+    }
+
     // The key argument is mutex with list
     if let Some(key) = arg_matches.get_one::<String>("key") {
         if arg_matches.get_flag("remove") {
             let old = mcnf.remove(key);
             if let Some(old) = old {
                 println!("Removed {key} with value {}", old.unwrap_or("<empty>".to_string()));
-                write(file, mcnf.to_vec())?;
+                data.store(&mcnf)?;
                 println!("Updated {}", file.display());
             }
 
         } else if arg_matches.get_flag("empty") {
             let old = mcnf.try_insert(key.clone(), None)?;
-            write(file, mcnf.to_vec())?;
+            data.store(&mcnf)?;
             println!("Added empty {key}. Previous value: {}", old.unwrap_or("n/a".to_string()));
         }
         else if let Some(value) = arg_matches.get_one::<String>("value") {
             let old = mcnf.try_insert(key.clone(), Some(value.clone()))?;
-            write(file, mcnf.to_vec())?;
+            data.store(&mcnf)?;
             println!("Added value {value} to  key {key}. Previous value: {}", old.unwrap_or("n/a".to_string()));
         } else {
             if let Some(value) = mcnf.get(key) {
@@ -131,3 +226,147 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Report the outcome of opening `file`, returning the handle on success.
+fn open_file(file: &Path, opened: Result<MConfigFile, MCError>) -> Result<MConfigFile, Box<dyn Error>> {
+    match opened {
+        Ok(f) => {
+            println!("Opened {}", file.display());
+            Ok(f)
+        }
+        Err(e @ MCError::Io(ErrorKind::WouldBlock)) => {
+            eprintln!("{} is locked by another process.", file.display());
+            Err(e.into())
+        }
+        Err(e) => {
+            eprintln!("Error loading {}: {}", file.display(), e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Build an empty MConfig with the secret and write it to `file` right away, so the file exists even if
+/// nothing else is done. The file is only touched once the MConfig is built, and a new file is removed
+/// again if writing it fails.
+fn create_file(file: &Path, secret: Option<&str>) -> Result<(MConfigFile, MConfig), Box<dyn Error>> {
+    let builder = match secret {
+        Some(secret) => MConfig::builder().secret(secret),
+        None => MConfig::builder(),
+    };
+    let mcnf = match builder.try_build() {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Failed to create MConfigurator data: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    let existed = file.exists();
+    let mut data = open_file(file, MConfigFile::create(file))?;
+    if let Err(e) = data.store(&mcnf) {
+        drop(data);
+        if !existed {
+            let _ = std::fs::remove_file(file);
+        }
+        eprintln!("Failed to write {}: {}", file.display(), e);
+        return Err(e.into());
+    }
+
+    println!("Created empty MConfigurator data in {}", file.display());
+    Ok((data, mcnf))
+}
+
+/// Keys and optional values read from an import file: in file order for .env, sorted by key for JSON.
+type ImportEntries = Vec<(String, Option<String>)>;
+
+/// Read the entries of an import file, choosing the format by its extension.
+fn read_import(path: &Path) -> Result<ImportEntries, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => parse_json_import(&text),
+        Some("env") => parse_env_import(&text),
+        _ => Err(format!("{}: unsupported import format, expected .json or .env", path.display()).into()),
+    }
+}
+
+/// Parse a flat JSON object of string keys to strings or null. The entries come out sorted by key.
+/// If a key appears more than once in the object, only its last value is kept.
+fn parse_json_import(text: &str) -> Result<ImportEntries, Box<dyn Error>> {
+    let serde_json::Value::Object(map) = serde_json::from_str(text)? else {
+        return Err("expected a JSON object".into());
+    };
+    map.into_iter()
+        .map(|(k, v)| match v {
+            serde_json::Value::String(s) => Ok((k, Some(s))),
+            serde_json::Value::Null => Ok((k, None)),
+            _ => Err(format!("{k}: expected a string or null").into()),
+        })
+        .collect()
+}
+
+/// Parse `KEY=VALUE` lines, with an optional `export` prefix and quotes around the value.
+/// A line with only a key gives a key without a value. Blank lines and lines starting with '#' are skipped.
+fn parse_env_import(text: &str) -> Result<ImportEntries, Box<dyn Error>> {
+    let mut entries = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((k, v)) => {
+                let v = v.trim();
+                let unquoted = [('"', '"'), ('\'', '\'')]
+                    .iter()
+                    .find_map(|&(open, close)| v.strip_prefix(open).and_then(|v| v.strip_suffix(close)));
+                (k.trim(), Some(unquoted.unwrap_or(v).to_string()))
+            }
+            None => (line, None),
+        };
+        if key.is_empty() {
+            return Err(format!("line {}: missing key", n + 1).into());
+        }
+        entries.push((key.to_string(), value));
+    }
+
+    Ok(entries)
+}
+
+/// Apply each line of a batch file to the MConfig, returning the number of operations.
+/// Blank lines and lines starting with '#' are skipped. Stops at the first failure.
+fn apply_batch(mcnf: &mut MConfig, ops: &str) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+
+    for (n, line) in ops.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (op, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let (key, value) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .map_or((rest.trim_start(), ""), |(k, v)| (k, v.trim_start()));
+        if key.is_empty() {
+            return Err(format!("line {}: missing key", n + 1).into());
+        }
+
+        let result = match op {
+            "set" => mcnf.try_insert(key.to_string(), Some(value.to_string())).map(|_| ()),
+            "empty" => mcnf.try_insert(key.to_string(), None).map(|_| ()),
+            "remove" => {
+                mcnf.remove(key);
+                Ok(())
+            }
+            _ => return Err(format!("line {}: unknown operation '{op}'", n + 1).into()),
+        };
+        result.map_err(|e| format!("line {}: {e}", n + 1))?;
+        count += 1;
+    }
+
+    Ok(count)
+}
@@ -31,20 +31,44 @@
 //! }
 //! ```
 //!
+mod mconfig_aead;
 mod mconfig_builder;
+mod mconfig_entry;
+mod mconfig_file;
+mod mconfig_header;
+mod mconfig_kdf;
+mod mconfig_mac;
+mod mconfig_query;
+mod mconfig_readonly;
+mod mconfig_records;
+#[cfg(feature = "regex")]
+mod mconfig_regex;
+#[cfg(feature = "serde")]
+mod mconfig_serde;
+#[cfg(feature = "sign")]
+mod mconfig_sign;
+mod mconfig_view;
 
 use crate::mconfigurator::mconfig_builder::MConfigBuilder;
+use crate::mconfigurator::mconfig_records::{push_len, read_len};
+pub use crate::mconfigurator::mconfig_entry::MConfigEntry;
+pub use crate::mconfigurator::mconfig_header::Header;
+pub use crate::mconfigurator::mconfig_file::MConfigFile;
+pub use crate::mconfigurator::mconfig_kdf::KdfParams;
+pub use crate::mconfigurator::mconfig_readonly::ReadOnlyMConfig;
+pub use crate::mconfigurator::mconfig_view::MConfigView;
 use rand;
 use std::collections::hash_map::Iter as HashMapIter;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Read, Write};
 use std::ops::Index;
 
 /// Errors that can be generated by MConfig.
 #[derive(Debug, PartialEq)]
 pub enum MCError {
     TooShort,
-    TooBig,
+    TooBig { over_by: usize },
     BadHeader,
     UnknownVersion,
     TruncatedKey,
@@ -53,6 +77,16 @@ pub enum MCError {
     InvalidUTF8,
     ValueTooBig,
     KeyTooBig,
+    Io(std::io::ErrorKind),
+    DuplicateKey,
+    UnknownAlgorithm,
+    InvalidFormat,
+    IntegrityFailure,
+    WrongSecret,
+    DecryptionFailed,
+    ChecksumMismatch,
+    EmptyKey,
+    EmptySecret,
 }
 
 impl Display for MCError {
@@ -61,114 +95,749 @@ impl Display for MCError {
     }
 }
 
+impl From<std::io::Error> for MCError {
+    fn from(e: std::io::Error) -> Self {
+        MCError::Io(e.kind())
+    }
+}
+
 impl std::error::Error for MCError {
 
 }
 
+/// How to resolve a key that is present more than once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicatePolicy {
+    /// The most recently seen value replaces earlier ones.
+    LastWins,
+    /// The first value seen is kept and later ones are ignored.
+    FirstWins,
+    /// Fail with `MCError::DuplicateKey`.
+    Error,
+}
+
+/// The obfuscation algorithm applied to the entries when a secret is set.
+/// Version 0 data is always XORed; later versions record the algorithm in the header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    /// No obfuscation; the entries are stored in the clear.
+    Plain,
+    /// The entries are XORed against the repeated bytes of the secret.
+    Xor,
+    /// Only the values are XORed against the secret. Keys stay readable in the serialized data,
+    /// e.g. so that files can be indexed by key without the secret.
+    XorValues,
+    /// The entries are encrypted and authenticated with ChaCha20-Poly1305, keyed with a hash of the
+    /// obfuscation key. Loading with the wrong secret or altered data fails with `DecryptionFailed`.
+    ChaCha20Poly1305,
+}
+
+impl Algorithm {
+    /// The header byte identifying this algorithm.
+    fn tag(self) -> u8 {
+        match self {
+            Algorithm::Plain => 0,
+            Algorithm::Xor => 1,
+            Algorithm::XorValues => 2,
+            Algorithm::ChaCha20Poly1305 => 3,
+        }
+    }
+
+    /// Look up an algorithm by its header byte.
+    fn from_tag(tag: u8) -> MCResult<Algorithm> {
+        match tag {
+            0 => Ok(Algorithm::Plain),
+            1 => Ok(Algorithm::Xor),
+            2 => Ok(Algorithm::XorValues),
+            3 => Ok(Algorithm::ChaCha20Poly1305),
+            _ => Err(MCError::UnknownAlgorithm),
+        }
+    }
+}
+
+/// The size tier of a serialized MConfig block, for reporting and comparing files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeClass {
+    /// 1,024 bytes.
+    Tiny1K,
+    /// 4,096 bytes.
+    Small4K,
+    /// 8,192 bytes, the default.
+    Default8K,
+    /// 16,384 bytes.
+    Large16K,
+    /// Any other size in bytes.
+    Custom(usize),
+}
+
+impl SizeClass {
+    /// The tier of a block of `size` bytes.
+    pub fn from_size(size: usize) -> SizeClass {
+        match size {
+            1_024 => SizeClass::Tiny1K,
+            4_096 => SizeClass::Small4K,
+            8_192 => SizeClass::Default8K,
+            16_384 => SizeClass::Large16K,
+            _ => SizeClass::Custom(size),
+        }
+    }
+
+    /// The block size in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            SizeClass::Tiny1K => 1_024,
+            SizeClass::Small4K => 4_096,
+            SizeClass::Default8K => 8_192,
+            SizeClass::Large16K => 16_384,
+            SizeClass::Custom(size) => size,
+        }
+    }
+}
+
 type MCHashMap = std::collections::HashMap<String, Option<String>>;
 pub type MCResult<T> = Result<T, MCError>;
 
-/// Key-value storage with optional secret
+/// Key-value storage with optional secret.
+/// Cloning copies the entries, secret and all settings, e.g. to snapshot a config before speculative edits.
+#[derive(Clone)]
 pub struct MConfig {
     version: u8,
+    algorithm: Algorithm,
     entries: MCHashMap,
     secret: Option<String>,
+    sorted: bool,
+    fingerprint: bool,
+    kdf: Option<KdfParams>,
+    block_size: usize,
+    rotate_after: Option<u64>,
 }
 
 impl MConfig {
     const MAGIC_HEADER_BYTES: [u8; 5] = [0x4d, 0x43, 0x4f, 0x4e, 0x46];
     const HEADER_SIZE: usize = MConfig::MAGIC_HEADER_BYTES.len() + 1;
     const VERSION_INDEX: usize = MConfig::MAGIC_HEADER_BYTES.len();
+    const ALGORITHM_INDEX: usize = MConfig::HEADER_SIZE;
+    const FLAGS_INDEX: usize = MConfig::ALGORITHM_INDEX + 1;
+    const V1_HEADER_SIZE: usize = MConfig::FLAGS_INDEX + 1;
+    const FINGERPRINT_FLAG: u8 = 0x01;
+    const FINGERPRINT_LEN: usize = 16;
+    const FINGERPRINT_SALT_LEN: usize = 8;
+    const KDF_FLAG: u8 = 0x02;
+    const BLOCK_SIZE_FLAG: u8 = 0x04;
+    const BLOCK_SIZE_LEN: usize = 4;
+    const ROTATE_AFTER_FLAG: u8 = 0x08;
+    const ROTATE_AFTER_LEN: usize = 8;
+    const AEAD_FLAG: u8 = 0x10;
+    const AEAD_LEN: usize = 12 + 16;
+    const SENTINEL_FLAG: u8 = 0x20;
+    const SENTINEL_LEN: usize = 4;
+    const CHECKSUM_FLAG: u8 = 0x40;
+    const CHECKSUM_LEN: usize = 4;
+    const MIN_BLOCK_SIZE: usize = 64;
+    const MAX_BLOCK_SIZE: usize = 1 << 24;
+    const ENTROPY_WINDOW: usize = 256;
+    const OBFUSCATED_ENTROPY: f64 = 5.0;
     const MCONFIG_SIZE: usize = 8_192;
     const MAX_KEY_LEN: usize = u8::MAX as usize;
     const MAX_VALUE_LEN: usize = u8::MAX as usize;
-    const LATEST_VERSION: u8 = 0;
+    const MAX_WIDE_LEN: usize = u16::MAX as usize;
+    const WIDE_LEN_PREFIX: usize = 2;
+    const DEFAULT_VERSION: u8 = 1;
+    const LATEST_VERSION: u8 = 2;
 
     /// Get a new Builder
     pub fn builder() -> MConfigBuilder {
         MConfigBuilder::new()
     }
 
+    /// Read exactly one serialized block from `r` and parse it, deobfuscating with the secret if given.
+    /// The header is read first to find out how long the block is.
+    pub fn from_reader<R: Read>(mut r: R, secret: Option<&str>) -> MCResult<MConfig> {
+        let mut raw = Vec::new();
+        let mut needed = MConfig::HEADER_SIZE;
+        while raw.len() < needed {
+            let start = raw.len();
+            raw.resize(needed, 0);
+            r.read_exact(&mut raw[start..])?;
+            needed = Header::required_len(&raw)?;
+        }
+
+        let start = raw.len();
+        raw.resize(Header::parse(&raw)?.block_size(), 0);
+        r.read_exact(&mut raw[start..])?;
+
+        MConfig::builder().optional_secret(secret).load(raw).try_build()
+    }
+
+    /// Build a new MConfig of the default version without a secret from key-value pairs.
+    /// This will fail under the same conditions as `try_insert`; a repeated key keeps its last value.
+    pub fn try_from_iter<I: IntoIterator<Item = (String, Option<String>)>>(iter: I) -> MCResult<MConfig> {
+        let mut mc = MConfig::builder().try_build()?;
+        for (k, v) in iter {
+            mc.try_insert(k, v)?;
+        }
+        Ok(mc)
+    }
+
+    /// Build a new MConfig from `key=value` lines, inserting entries until it is full instead of failing.
+    /// A line without `=` becomes a valueless entry and blank lines are ignored. An entry that would make
+    /// the block exceed its size is skipped; if `stop_when_full` is set, reading stops there, otherwise later
+    /// lines that still fit are inserted. Returns the MConfig with the number of entries imported and skipped.
+    /// Fails on a read error, or with `KeyTooBig` or `ValueTooBig` for an entry that can never fit.
+    pub fn from_lines_limited<R: BufRead>(
+        r: R,
+        secret: Option<&str>,
+        stop_when_full: bool,
+    ) -> MCResult<(MConfig, usize, usize)> {
+        let mut mc = MConfig::builder().optional_secret(secret).try_build()?;
+        let (mut imported, mut skipped) = (0, 0);
+
+        for line in r.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k, Some(v.to_string())),
+                None => (line.as_str(), None),
+            };
+            match mc.try_insert(key.to_string(), value) {
+                Ok(_) => imported += 1,
+                Err(MCError::TooBig { .. }) => {
+                    skipped += 1;
+                    if stop_when_full {
+                        break;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok((mc, imported, skipped))
+    }
+
+    /// Build a new MConfig from key-value pairs where every key has a value.
+    /// This will fail under the same conditions as `try_insert`.
+    pub fn from_str_pairs(pairs: &[(&str, &str)], secret: Option<&str>) -> MCResult<MConfig> {
+        let mut mc = MConfig::builder().optional_secret(secret).try_build()?;
+        for (k, v) in pairs {
+            mc.try_insert(k.to_string(), Some(v.to_string()))?;
+        }
+        Ok(mc)
+    }
+
+    /// Serialize an empty MConfig of the default version, e.g. to provision a new file.
+    pub fn empty_blob(secret: Option<&str>) -> Vec<u8> {
+        MConfig::builder()
+            .optional_secret(secret)
+            .try_build()
+            .expect("an empty MConfig can always be built")
+            .to_vec()
+    }
+
+    /// Construct an MConfig of the default version from ordered entries, which are also written in key order.
+    /// This will fail like `try_insert` if any entry is too long or the entries do not fit together.
+    pub fn with_entries(entries: BTreeMap<String, Option<String>>, secret: Option<String>) -> MCResult<MConfig> {
+        let mut mc = MConfig::builder().optional_secret(secret.as_deref()).try_build()?;
+        for (k, v) in entries {
+            mc.try_insert(k, v)?;
+        }
+        mc.normalize();
+        Ok(mc)
+    }
+
+    /// Parse several serialized blocks with the same secret and combine their entries into one MConfig.
+    /// Keys present in more than one block are resolved according to `on_duplicate`.
+    /// This will fail if any block fails to parse or if the combined entries do not fit.
+    pub fn merge_files(blobs: &[&[u8]], secret: Option<&str>, on_duplicate: DuplicatePolicy) -> MCResult<MConfig> {
+        let mut merged = MConfig::builder().optional_secret(secret).try_build()?;
+
+        for blob in blobs {
+            let mc = MConfig::builder().optional_secret(secret).try_build_ref(blob)?;
+            for (k, v) in mc {
+                if merged.contains_key(&k) {
+                    match on_duplicate {
+                        DuplicatePolicy::LastWins => {}
+                        DuplicatePolicy::FirstWins => continue,
+                        DuplicatePolicy::Error => return Err(MCError::DuplicateKey),
+                    }
+                }
+                merged.try_insert(k, v)?;
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Parse serialized data, insert a single entry, and return the re-serialized data.
+    /// This will fail if the data fails to parse or under the same conditions as `try_insert`.
+    pub fn blob_with_entry(bytes: &[u8], secret: Option<&str>, key: &str, value: Option<&str>) -> MCResult<Vec<u8>> {
+        let mut mc = MConfig::builder().optional_secret(secret).try_build_ref(bytes)?;
+        mc.try_insert(key.to_string(), value.map(|v| v.to_string()))?;
+        Ok(mc.to_vec())
+    }
+
+    /// Compare the entries of two serialized blocks, ignoring padding, entry order and header differences.
+    /// Useful for checking whether a stored config changed, since the raw bytes differ on every save.
+    /// This will fail if either block fails to parse.
+    pub fn blobs_equal(a: &[u8], b: &[u8], secret: Option<&str>) -> MCResult<bool> {
+        let a = MConfig::builder().optional_secret(secret).try_build_ref(a)?;
+        let b = MConfig::builder().optional_secret(secret).try_build_ref(b)?;
+        Ok(a.entries == b.entries)
+    }
+
+    /// Load serialized data only if `secret` matches the fingerprint stored in its header, failing with
+    /// `WrongSecret` otherwise before anything is deobfuscated. Data without a fingerprint is loaded as
+    /// `try_build` would, so a wrong secret is then only caught by the sentinel, the cipher, or not at all in v0.
+    pub fn load_verified(bytes: &[u8], secret: &str) -> MCResult<MConfig> {
+        MConfig::builder().secret(secret).try_build_ref(bytes)
+    }
+
+    /// Parse only the header of serialized data, e.g. to index files by version or fingerprint.
+    /// No secret is needed, since the header is never obfuscated, and the entries are not read.
+    pub fn read_header(bytes: &[u8]) -> MCResult<Header> {
+        Header::parse(bytes)
+    }
+
+    /// Shannon entropy in bits per byte of the start of the entries region of a serialized MConfig.
+    /// The padding after the entries is random either way, so only the first `ENTROPY_WINDOW` bytes,
+    /// where entries are stored, are measured. If the header does not parse, the data is measured from the start.
+    pub fn blob_entropy(bytes: &[u8]) -> f64 {
+        let start = Header::parse(bytes).map_or(0, |h| h.size());
+        let window = &bytes[start.min(bytes.len())..];
+        let window = &window[..window.len().min(MConfig::ENTROPY_WINDOW)];
+        if window.is_empty() {
+            return 0.0;
+        }
+
+        let mut counts = [0usize; 256];
+        for b in window {
+            counts[*b as usize] += 1;
+        }
+        let len = window.len() as f64;
+        counts
+            .iter()
+            .filter(|c| **c > 0)
+            .map(|c| {
+                let p = *c as f64 / len;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    /// Guess whether serialized data was obfuscated with a secret, e.g. to suggest that a secret was forgotten.
+    /// This is a heuristic based on `blob_entropy`: plaintext keys and values have noticeably lower entropy
+    /// than obfuscated ones. It can be wrong, particularly with only a few short entries or a very short secret.
+    pub fn is_probably_obfuscated(bytes: &[u8]) -> bool {
+        MConfig::blob_entropy(bytes) > MConfig::OBFUSCATED_ENTROPY
+    }
+
+    /// Write the serialized block to `w`. It will be obfuscated if there is a secret configured.
+    /// Fails with `io::ErrorKind::InvalidInput` and nothing written if the contents do not fit (see `try_to_vec`).
+    pub fn to_writer<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        let v = self
+            .try_to_vec()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        w.write_all(&v)
+    }
+
     /// Return a `Vec<u8>` of the MConfig. It will be obfuscated if there is a secret configured.
+    /// Panics if the contents do not fit, which can happen if settings that grow the header are changed
+    /// after the entries were added; use `try_to_vec` to get an error instead.
     pub fn to_vec(&self) -> Vec<u8> {
-        let mut v: Vec<u8> = Vec::with_capacity(MConfig::MCONFIG_SIZE);
-        v.append(&mut MConfig::MAGIC_HEADER_BYTES.to_vec());
-        v.push(self.version);
-        let mut e = MConfig::obfuscate(self.entries_to_vec(), &self.secret, self.version);
+        self.try_to_vec()
+            .unwrap_or_else(|e| panic!("MConfig contents cannot be serialized: {}", e))
+    }
+
+    /// Return a `Vec<u8>` of the MConfig like `to_vec`, or an error if the contents do not fit, see `validate`.
+    pub fn try_to_vec(&self) -> MCResult<Vec<u8>> {
+        self.validate()?;
+
+        let mut header = self.header();
+        let key = header.key(self.secret.as_deref());
+        let mut e = MConfig::obfuscate(self.entries_to_vec(), key.as_deref(), &mut header);
+        let mut v: Vec<u8> = Vec::with_capacity(self.block_size);
+        v.append(&mut header.to_vec());
         v.append(&mut e);
-        assert_eq!(v.len(), MConfig::MCONFIG_SIZE);
-        v
+        assert_eq!(v.len(), self.block_size);
+        Ok(v)
+    }
+
+    /// Check that the contents can be serialized: fails with `KeyTooBig` or `ValueTooBig` for an entry over
+    /// the limits of the version, `TooBig` if the entries and header together exceed the block size,
+    /// or `EmptySecret` if the secret is empty rather than `None`.
+    /// `try_insert` keeps these invariants, but edits through `get_mut` or `values_mut` do not.
+    pub fn validate(&self) -> MCResult<()> {
+        if self.secret.as_deref() == Some("") {
+            return Err(MCError::EmptySecret);
+        }
+        for (k, v) in &self.entries {
+            self.check_entry(k, v)?;
+        }
+        let used = self.used_len(None);
+        if used > self.block_size {
+            return Err(MCError::TooBig { over_by: used - self.block_size });
+        }
+        Ok(())
+    }
+
+    /// Check a key and value against the length limits of the current version, see `check_entry_for`.
+    fn check_entry(&self, key: &str, value: &Option<String>) -> MCResult<()> {
+        MConfig::check_entry_for(self.version, key, value)
+    }
+
+    /// Check a key and value against the length limits of `version`.
+    /// Empty keys are rejected, since a zero key length is read as the end of the entries.
+    fn check_entry_for(version: u8, key: &str, value: &Option<String>) -> MCResult<()> {
+        if key.is_empty() {
+            return Err(MCError::EmptyKey);
+        }
+        if key.len() > MConfig::key_len_limit(version) {
+            return Err(MCError::KeyTooBig);
+        }
+        if value.as_ref().is_some_and(|v| v.len() > MConfig::value_len_limit(version)) {
+            return Err(MCError::ValueTooBig);
+        }
+        Ok(())
+    }
+
+    /// Serialize like `try_to_vec`, then parse the result again with the same secret and check that the
+    /// entries come back unchanged, failing with `IntegrityFailure` if they do not.
+    /// This doubles the work, so it is meant for callers that want to rule out a format bug at runtime.
+    pub fn serialize_verified(&self) -> MCResult<Vec<u8>> {
+        self.verify_serialized(self.try_to_vec()?)
+    }
+
+    /// Check that `bytes` parse back to the entries of this MConfig.
+    fn verify_serialized(&self, bytes: Vec<u8>) -> MCResult<Vec<u8>> {
+        let reparsed = MConfig::builder()
+            .optional_secret(self.secret.as_deref())
+            .on_duplicate(DuplicatePolicy::Error)
+            .try_build_ref(&bytes)
+            .map_err(|_| MCError::IntegrityFailure)?;
+
+        if reparsed.entries != self.entries {
+            return Err(MCError::IntegrityFailure);
+        }
+        Ok(bytes)
+    }
+
+    /// Return the records of the entries sorted by key and the terminator, without header or padding.
+    /// This is a stable input for external hashing or signing: it depends only on the entries, not on
+    /// the version, algorithm, secret or other header settings. Lengths always take two bytes, as in version 2,
+    /// so that entries of any version can be represented.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<(&String, &Option<String>)> = self.entries.iter().collect();
+        entries.sort();
+        MConfig::records_to_vec(entries, MConfig::WIDE_LEN_PREFIX)
+    }
+
+    /// Return a hex and ASCII dump of `to_vec` output for debugging the format, 16 bytes per line with offsets.
+    /// The header, sentinel, entries, terminator and padding are dumped as separately labelled sections.
+    /// Obfuscated bytes are dumped as written, so only the section boundaries are readable then.
+    /// Panics if the contents do not fit, like `to_vec`.
+    pub fn to_hex_dump(&self) -> String {
+        let bytes = self.to_vec();
+        let header = Header::parse(&bytes).expect("to_vec output has a valid header");
+        let prefix = MConfig::len_prefix_size(self.version);
+        let terminator = self.used_len(None) - prefix;
+
+        let sections = [
+            (format!("header, version {}", header.version()), 0, header.size()),
+            ("sentinel".to_string(), header.size(), header.reserved_len()),
+            (format!("entries, {}", self.entries.len()), header.reserved_len(), terminator),
+            ("terminator".to_string(), terminator, terminator + prefix),
+            ("padding".to_string(), terminator + prefix, bytes.len()),
+        ];
+
+        let mut dump = String::new();
+        for (label, start, end) in sections.iter().filter(|(_, start, end)| start < end) {
+            dump.push_str(&format!("-- {label}: {} bytes at {start:#06x} --\n", end - start));
+            for (row, chunk) in bytes[*start..*end].chunks(16).enumerate() {
+                let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+                let ascii: String = chunk
+                    .iter()
+                    .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                    .collect();
+                dump.push_str(&format!("{:08x}  {:<47}  |{ascii}|\n", start + row * 16, hex.join(" ")));
+            }
+        }
+        dump
+    }
+
+    /// Return the plaintext entries region as `to_vec` lays it out, in the same order, up to and including
+    /// the terminator. Unlike `canonical_bytes` this follows the `sorted` setting, and unlike `to_vec`
+    /// it leaves out the header and the random padding.
+    pub fn entries_bytes(&self) -> Vec<u8> {
+        let mut entries: Vec<(&String, &Option<String>)> = self.entries.iter().collect();
+        if self.sorted {
+            entries.sort();
+        }
+        MConfig::records_to_vec(entries, MConfig::len_prefix_size(self.version))
     }
 
     /// Return a Vec<u8> of the entries that is not obfuscated.
     fn entries_to_vec(&self) -> Vec<u8> {
-        let mut v: Vec<u8> = Vec::new();
+        let mut v = self.entries_bytes();
+
+        let reserved = self.header().reserved_len();
+        assert!(v.len() <= self.block_size - reserved);
+
+        //pad the rest with random, leaving space for a header
+        for _ in v.len()..self.block_size - reserved {
+            v.push(rand::random::<u8>());
+        }
+
+        v
+    }
 
-        for (entry_k, entry_v) in self.entries.iter() {
-            assert!(entry_k.len() <= MConfig::MAX_KEY_LEN);
+    /// Serialize entries as records in the given order, followed by the terminator.
+    /// Each length takes `prefix` bytes, little-endian.
+    fn records_to_vec(entries: Vec<(&String, &Option<String>)>, prefix: usize) -> Vec<u8> {
+        let mut v: Vec<u8> = Vec::new();
 
-            v.push(entry_k.len() as u8);
+        for (entry_k, entry_v) in entries {
+            push_len(&mut v, entry_k.len(), prefix);
             v.append(&mut entry_k.as_bytes().to_vec());
 
             if let Some(val) = entry_v {
-                assert!(val.len() <= MConfig::MAX_VALUE_LEN);
-                v.push(val.len() as u8);
+                push_len(&mut v, val.len(), prefix);
                 v.append(&mut val.as_bytes().to_vec());
             } else {
-                v.push(0);
+                push_len(&mut v, 0, prefix);
             }
         }
-        v.push(0); //end of data
-        assert!(v.len() <= MConfig::MCONFIG_SIZE - MConfig::HEADER_SIZE);
-
-        //pad the rest with random, leaving space for a header
-        for _ in v.len()..MConfig::MCONFIG_SIZE - MConfig::HEADER_SIZE {
-            v.push(rand::random::<u8>());
-        }
-
+        push_len(&mut v, 0, prefix); //end of data
         v
     }
 
     /// Insert a key-value pair. The value is optional.
-    /// This will fail if the key, the value is too long or if the addition would make the overall length
-    /// exceed MCONFIG_SIZE.
+    /// This will fail if the key is empty, since a zero key length marks the end of the entries, if the key
+    /// or the value is too long, or if the addition would make the overall length exceed the block size.
     /// Returns old value if Ok and key was present.
-    pub fn try_insert(&mut self, key: String, value: Option<String>) -> MCResult<Option<String>> {
-        if key.len() > MConfig::MAX_KEY_LEN {
-            return Err(MCError::KeyTooBig);
-        }
-        if let Some(ref val) = value {
-            if val.len() > MConfig::MAX_VALUE_LEN {
-                return Err(MCError::ValueTooBig);
-            }
-        }
+    /// The key can be anything that converts into a `String`; see `insert_str` for borrowed values.
+    pub fn try_insert(&mut self, key: impl Into<String>, value: Option<String>) -> MCResult<Option<String>> {
+        let key: String = key.into();
+        self.check_entry(&key, &value)?;
 
-        //check overall length if the new entry is added.
-        let overall_len = key.len()
-            + 1
-            + match value {
-                Some(ref v) => v.len() + 1,
-                None => 1,
-            }
-            + self
-                .entries
-                .iter()
-                .fold(MConfig::HEADER_SIZE, |acc, (k, v)| {
-                    acc + k.len()
-                        + 1
-                        + match v {
-                            Some(v) => v.len() + 1,
-                            None => 1,
-                        }
-                });
-
-        if overall_len < MConfig::MCONFIG_SIZE {
+        //check overall length if the new entry is added, replacing any existing entry for key.
+        let prefix = MConfig::len_prefix_size(self.version);
+        let overall_len = self.used_len(Some(&key)) + MConfig::entry_len(&key, &value, prefix);
+
+        if overall_len <= self.block_size {
             Ok(self.entries.insert(key, value).unwrap_or(None))
         } else {
-            Err(MCError::TooBig)
+            #[cfg(feature = "log")]
+            log::debug!(
+                "Rejected insert of {} bytes; the data would grow to {} of {} bytes",
+                MConfig::entry_len(&key, &value, prefix),
+                overall_len,
+                self.block_size
+            );
+            Err(MCError::TooBig { over_by: overall_len - self.block_size })
         }
     }
 
+    /// Insert a key-value pair from string slices, e.g. `insert_str("Hello", Some("World"))`.
+    /// This fails under the same conditions as `try_insert`.
+    pub fn insert_str(&mut self, key: impl Into<String>, value: Option<&str>) -> MCResult<Option<String>> {
+        self.try_insert(key, value.map(str::to_string))
+    }
+
+    /// Insert every pair from `iter`, replacing existing values; a key repeated in `iter` keeps its last value.
+    /// This is atomic: all entries are checked together first, and nothing is inserted if any of them fails
+    /// with `KeyTooBig` or `ValueTooBig`, or if they would not all fit together, with `TooBig`.
+    pub fn try_extend<I: IntoIterator<Item = (String, Option<String>)>>(&mut self, iter: I) -> MCResult<()> {
+        let mut extended = self.entries.clone();
+        for (k, v) in iter {
+            self.check_entry(&k, &v)?;
+            extended.insert(k, v);
+        }
+        let used = MConfig::used_len_for(&extended, &self.header(), None);
+        if used > self.block_size {
+            return Err(MCError::TooBig { over_by: used - self.block_size });
+        }
+
+        self.entries = extended;
+        Ok(())
+    }
+
+    /// Insert all `pairs` or none of them, like `try_extend`: every entry and the total size against the
+    /// block size are checked before anything is inserted.
+    pub fn try_insert_many(&mut self, pairs: Vec<(String, Option<String>)>) -> MCResult<()> {
+        self.try_extend(pairs)
+    }
+
+    /// Insert a key-value pair, overwriting any existing value, like `Add` in the C# interface.
+    /// This fails under the same conditions as `try_insert`.
+    pub fn add(&mut self, key: &str, value: Option<&str>) -> MCResult<()> {
+        self.try_insert(key.to_string(), value.map(|v| v.to_string())).map(|_| ())
+    }
+
+    /// Setter counterpart to the `Index` getter, like assigning `cfg[key] = value` through the C# indexer.
+    /// This is the same as `add`; Rust's `IndexMut` cannot be used because the insert can fail.
+    pub fn set_index(&mut self, key: &str, value: Option<&str>) -> MCResult<()> {
+        self.add(key, value)
+    }
+
+    /// Get the largest value length in bytes that could be stored at `key` given the current contents.
+    /// This accounts for any value already stored at `key`, and is capped at `max_value_len`.
+    /// Returns 0 if the key cannot be inserted at all, i.e. it is empty, too long, or would not fit even
+    /// without a value.
+    pub fn max_value_len_for(&self, key: &str) -> usize {
+        if self.check_entry(key, &None).is_err() {
+            return 0;
+        }
+        let used = self.used_len(Some(key)) + MConfig::entry_len(key, &None, MConfig::len_prefix_size(self.version));
+
+        self.block_size
+            .saturating_sub(used)
+            .min(self.max_value_len())
+    }
+
+    /// The serialized length of a single entry, including both lengths of `prefix` bytes each.
+    fn entry_len(key: &str, value: &Option<String>, prefix: usize) -> usize {
+        key.len() + prefix + value.as_ref().map_or(0, |v| v.len()) + prefix
+    }
+
+    /// The serialized length of the header, entries, and terminator (i.e., excluding padding).
+    /// The entry at `skip_key`, if any, is left out of the total.
+    fn used_len(&self, skip_key: Option<&str>) -> usize {
+        MConfig::used_len_for(&self.entries, &self.header(), skip_key)
+    }
+
+    /// The serialized length of `entries` behind `header` and any sentinel, excluding padding.
+    /// The record layout follows the version of `header`.
+    fn used_len_for(entries: &MCHashMap, header: &Header, skip_key: Option<&str>) -> usize {
+        let prefix = header.len_prefix_size();
+        entries
+            .iter()
+            .filter(|(k, _)| Some(k.as_str()) != skip_key)
+            .fold(header.reserved_len() + prefix, |acc, (k, v)| acc + MConfig::entry_len(k, v, prefix))
+    }
+
+    /// The header `to_vec` writes for the current settings.
+    fn header(&self) -> Header {
+        self.header_for(self.version)
+    }
+
+    /// The header `to_vec` would write for the current settings in the given version.
+    /// v0 has no room for an algorithm or optional fields, and nothing is obfuscated without a secret.
+    fn header_for(&self, version: u8) -> Header {
+        let block_size = (version > 0 && self.block_size != MConfig::MCONFIG_SIZE).then_some(self.block_size);
+        let rotate_after = self.rotate_after.filter(|_| version > 0);
+        let (algorithm, fingerprint, kdf) = match (&self.secret, version) {
+            (None, _) => (Algorithm::Plain, None, None),
+            (Some(_), 0) => (Algorithm::Xor, None, None),
+            (Some(_), _) => (
+                self.algorithm,
+                // filled in from the key when the entries are obfuscated
+                self.fingerprint.then_some([0u8; MConfig::FINGERPRINT_LEN]),
+                match self.algorithm {
+                    // the cipher key is always derived, never a bare hash of the secret
+                    Algorithm::ChaCha20Poly1305 => Some(self.kdf.unwrap_or_default()),
+                    _ => self.kdf,
+                },
+            ),
+        };
+
+        let mut flags = 0;
+        if fingerprint.is_some() {
+            flags |= MConfig::FINGERPRINT_FLAG;
+        }
+        if kdf.is_some() {
+            flags |= MConfig::KDF_FLAG;
+        }
+        if block_size.is_some() {
+            flags |= MConfig::BLOCK_SIZE_FLAG;
+        }
+        if rotate_after.is_some() {
+            flags |= MConfig::ROTATE_AFTER_FLAG;
+        }
+        // room for the nonce and tag, filled in when the entries are sealed
+        let aead = (algorithm == Algorithm::ChaCha20Poly1305).then_some([0u8; MConfig::AEAD_LEN]);
+        if aead.is_some() {
+            flags |= MConfig::AEAD_FLAG;
+        }
+        if version > 0 && algorithm == Algorithm::Xor {
+            flags |= MConfig::SENTINEL_FLAG;
+        }
+        // computed when the entries are obfuscated
+        let checksum = (version > 0).then_some(0);
+        if checksum.is_some() {
+            flags |= MConfig::CHECKSUM_FLAG;
+        }
+
+        Header {
+            version,
+            algorithm,
+            flags,
+            fingerprint,
+            kdf,
+            block_size,
+            rotate_after,
+            aead,
+            checksum,
+        }
+    }
+
+    /// The length of the fixed part of the header in the given version.
+    fn header_size(version: u8) -> usize {
+        match version {
+            0 => MConfig::HEADER_SIZE,
+            _ => MConfig::V1_HEADER_SIZE,
+        }
+    }
+
+    /// The number of bytes of each key or value length in the records of the given version.
+    /// Versions 0 and 1 use a single byte, version 2 two bytes in little-endian order.
+    fn len_prefix_size(version: u8) -> usize {
+        match version {
+            0 | 1 => 1,
+            _ => MConfig::WIDE_LEN_PREFIX,
+        }
+    }
+
+    /// The largest key length in bytes for the given version.
+    fn key_len_limit(version: u8) -> usize {
+        match MConfig::len_prefix_size(version) {
+            1 => MConfig::MAX_KEY_LEN,
+            _ => MConfig::MAX_WIDE_LEN,
+        }
+    }
+
+    /// The largest value length in bytes for the given version.
+    fn value_len_limit(version: u8) -> usize {
+        match MConfig::len_prefix_size(version) {
+            1 => MConfig::MAX_VALUE_LEN,
+            _ => MConfig::MAX_WIDE_LEN,
+        }
+    }
+
+    /// The largest key length in bytes that the format version in use can store.
+    pub fn max_key_len(&self) -> usize {
+        MConfig::key_len_limit(self.version)
+    }
+
+    /// The largest value length in bytes that the format version in use can store.
+    pub fn max_value_len(&self) -> usize {
+        MConfig::value_len_limit(self.version)
+    }
+
+    /// Insert a key-value pair given as raw bytes, such as from an external source.
+    /// This will fail with `InvalidUTF8` if either the key or the value is not valid UTF-8,
+    /// and otherwise behaves like `try_insert`.
+    pub fn try_insert_bytes_key(&mut self, key: &[u8], value: Option<&[u8]>) -> MCResult<()> {
+        let key = std::str::from_utf8(key).map_err(|_| MCError::InvalidUTF8)?;
+        let value = match value {
+            Some(v) => Some(std::str::from_utf8(v).map_err(|_| MCError::InvalidUTF8)?),
+            None => None,
+        };
+
+        self.try_insert(key.to_string(), value.map(|v| v.to_string()))?;
+        Ok(())
+    }
+
     /// Try to retrieve a value at key. Will fail if the key is not present.
     pub fn try_get(&self, key: &str) -> MCResult<&Option<String>> {
         self.entries.get(key).ok_or(MCError::MissingKey)
@@ -179,6 +848,43 @@ impl MConfig {
         self.entries.get(key)
     }
 
+    /// Retrieve a mutable reference to the value at key, to change it in place.
+    /// Changes made this way skip the length checks of `try_insert`; a value that ends up too long
+    /// makes `try_to_vec` fail and `to_vec` panic. Use `try_set_value` to have the new value checked.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Option<String>> {
+        self.entries.get_mut(key)
+    }
+
+    /// Replace the value of an existing key after checking that it fits, like `try_insert`.
+    /// Fails with `MissingKey` if the key is not set. Returns the old value.
+    pub fn try_set_value(&mut self, key: &str, value: Option<String>) -> MCResult<Option<String>> {
+        if !self.entries.contains_key(key) {
+            return Err(MCError::MissingKey);
+        }
+        self.try_insert(key.to_string(), value)
+    }
+
+    /// Retrieve the value at key, or insert the value computed by `f` if the key is not set.
+    /// `f` is only called if the key is absent. The insert can fail like `try_insert`, leaving the key unset.
+    pub fn get_or_insert_with<F: FnOnce() -> Option<String>>(&mut self, key: String, f: F) -> MCResult<&Option<String>> {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Retrieve the value at a key that matches `key` ignoring case. An exact match is preferred.
+    /// Unlike `get`, this scans every key (O(n)), but stored keys keep their original case.
+    /// If several keys differ only by case, which one is returned is unspecified.
+    pub fn get_ignore_case(&self, key: &str) -> Option<&Option<String>> {
+        if let Some(v) = self.entries.get(key) {
+            return Some(v);
+        }
+
+        let key = key.to_lowercase();
+        self.entries
+            .iter()
+            .find(|(k, _)| k.to_lowercase() == key)
+            .map(|(_, v)| v)
+    }
+
     /// Check if a given key is present.
     pub fn contains_key(&self, key: &str) -> bool {
         self.entries.contains_key(key)
@@ -189,115 +895,1850 @@ impl MConfig {
         self.entries.remove(key)
     }
 
+    /// Remove the value at key while keeping the key. Fails with `MissingKey` if the key is not present.
+    /// Returns true if a value was cleared, or false if the key was already valueless.
+    pub fn clear_value(&mut self, key: &str) -> MCResult<bool> {
+        match self.entries.get_mut(key) {
+            Some(value) => Ok(value.take().is_some()),
+            None => Err(MCError::MissingKey),
+        }
+    }
+
+    /// Remove a key if present, overwriting its value with zeros before the memory is freed.
+    /// Returns true if the key was present.
+    pub fn secure_remove(&mut self, key: &str) -> bool {
+        match self.entries.remove(key) {
+            Some(value) => {
+                if let Some(value) = value {
+                    MConfig::scrub(value);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Overwrite the bytes of a string with zeros and drop it.
+    fn scrub(s: String) {
+        let mut bytes = s.into_bytes();
+        for b in bytes.iter_mut() {
+            // SAFETY: b is a valid, aligned &mut u8; the volatile write keeps the zeroing from being optimized out
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// Remove all entries, keeping the secret, version and other settings.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
     /// Get the number of elements in the collection.
     pub fn len(&self) -> usize {
         self.entries.len()
     }
 
-    /// Change the secret used during obfuscation.
-    pub fn set_secret(&mut self, secret: Option<String>) {
-        self.secret = secret;
+    /// Check if the collection has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
     }
 
-    /// Applies the obfuscation algorithm if a secret is set.
-    fn obfuscate(buffer: Vec<u8>, secret: &Option<String>, _version: u8) -> Vec<u8> {
-        match secret {
-            Some(ref secret) => MConfig::xor_buffer(buffer.clone(), secret.as_bytes().to_vec()),
-            None => buffer,
+    /// Change the format version used by `to_vec`, e.g. to keep writing v0 for older readers.
+    /// This will fail if the version is unknown or if the current contents cannot be represented in it,
+    /// in which case the version is left unchanged. Version 0 only supports the default block size
+    /// and fails with `InvalidFormat` otherwise.
+    pub fn set_version(&mut self, version: u8) -> MCResult<()> {
+        if !MConfig::is_supported_version(version) {
+            return Err(MCError::UnknownVersion);
+        }
+        if version == 0 && self.block_size != MConfig::MCONFIG_SIZE {
+            return Err(MCError::InvalidFormat);
         }
-    }
 
-    /// Applies the deobfuscation algorithm if a secret is set.
-    fn deobfuscate(buffer: Vec<u8>, secret: &Option<String>, _version: u8) -> Vec<u8> {
-        match secret {
-            Some(ref secret) => MConfig::xor_buffer(buffer.clone(), secret.as_bytes().to_vec()),
-            None => buffer,
+        for (k, v) in &self.entries {
+            MConfig::check_entry_for(version, k, v)?;
         }
+        let used = MConfig::used_len_for(&self.entries, &self.header_for(version), None);
+        if used > self.block_size {
+            return Err(MCError::TooBig { over_by: used - self.block_size });
+        }
+
+        self.version = version;
+        Ok(())
     }
 
-    /// The algorithm used in v0. This is reversible so it is used for both ob- and deobfuscation.
-    /// This simply XORs the bytes of data against the bytes of the secret.
-    /// In theory, if the secret were longer than MCONFIG_SIZE, the actual obfuscation would be unbreakable if
-    /// only used once (e.g., one-time pad) but the nature of this whole implementation precludes that sort of security.
-    fn xor_buffer(mut buf: Vec<u8>, secret: Vec<u8>) -> Vec<u8> {
-        for (b, s) in buf.iter_mut().zip(secret.iter().cycle()) {
-            *b ^= s;
-        }
-        buf
+    /// The format version `to_vec` writes: that of the loaded data, or the default 1 for a new MConfig.
+    pub fn version(&self) -> u8 {
+        self.version
     }
 
-    /// Helper function to get an Iterator
-    pub fn iter(&self) -> MConfigIter {
-        MConfigIter::new(self)
+    /// The length in bytes of the block `to_vec` writes. The default is 8,192.
+    pub fn block_size(&self) -> usize {
+        self.block_size
     }
-}
 
-/// Index notation support
-/// Returns a reference to the value at the key/index.
-/// #Panics
-/// Panics if the key is not present.
-impl Index<&str> for MConfig {
-    type Output = Option<String>;
+    /// Change the length in bytes of the block `to_vec` writes, between 64 bytes and 16 MiB.
+    /// Sizes other than the default are recorded in the header, so they need version 1 or later.
+    /// This fails with `InvalidFormat` if the size is out of range or not supported by the version,
+    /// or with `TooBig` if the current contents do not fit, in which case the size is left unchanged.
+    pub fn set_block_size(&mut self, size: usize) -> MCResult<()> {
+        if !MConfig::is_valid_block_size(size) || (self.version == 0 && size != MConfig::MCONFIG_SIZE) {
+            return Err(MCError::InvalidFormat);
+        }
 
-    fn index(&self, index: &str) -> &Self::Output {
-        &self.entries[index]
+        let old = std::mem::replace(&mut self.block_size, size);
+        let used = self.used_len(None);
+        if used > size {
+            self.block_size = old;
+            return Err(MCError::TooBig { over_by: used - size });
+        }
+        Ok(())
     }
-}
-
-/// Iterator support
-pub struct MConfigIter<'a> {
-    mc_iter: HashMapIter<'a, String, Option<String>>,
-}
 
-impl MConfigIter<'_> {
-    fn new(mconfig: &MConfig) -> MConfigIter {
-        MConfigIter {
-            mc_iter: mconfig.entries.iter(),
+    /// Change the block size to the smallest standard `SizeClass` below the current block size that the
+    /// contents fit in and return the new size, e.g. to reclaim space after removing entries. The block size
+    /// is kept if no smaller class fits, so this never grows the block. Version 0 only supports the default size.
+    /// This fails with `TooBig` if the contents do not fit the current block size, leaving it unchanged.
+    pub fn shrink_to_smallest(&mut self) -> MCResult<usize> {
+        let current = self.block_size;
+        let classes = [SizeClass::Tiny1K, SizeClass::Small4K, SizeClass::Default8K, SizeClass::Large16K];
+        for class in classes.iter().filter(|c| c.size() < current) {
+            if self.set_block_size(class.size()).is_ok() {
+                return Ok(class.size());
+            }
         }
+        self.set_block_size(current).map(|_| current)
     }
-}
 
-impl<'a> Iterator for MConfigIter<'a> {
-    type Item = (&'a String, &'a Option<String>);
+    /// Record when the secret of this file should next be rotated, as a Unix timestamp in seconds.
+    /// This is metadata for tooling only and does not affect the entries. It is stored in the header,
+    /// so it needs version 1 or later, and takes 8 bytes of the block.
+    pub fn set_rotate_after(&mut self, unix_ts: u64) {
+        self.rotate_after = Some(unix_ts);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.mc_iter.next()
+    /// When the secret should next be rotated, as a Unix timestamp in seconds, if recorded.
+    pub fn rotate_after(&self) -> Option<u64> {
+        self.rotate_after
     }
-}
 
-/// Convert a plain hashmap to an MConfig
-/// This sets up the object with the latest version and no secret.
-impl TryFrom<std::collections::HashMap<String, Option<String>>> for MConfig {
-    type Error = MCError;
+    /// Check if a block size can be written and read.
+    fn is_valid_block_size(size: usize) -> bool {
+        (MConfig::MIN_BLOCK_SIZE..=MConfig::MAX_BLOCK_SIZE).contains(&size)
+    }
 
-    fn try_from(value: HashMap<String, Option<String>>) -> Result<Self, Self::Error> {
-        let mut total_len: usize = 0;
+    /// Check if this implementation can read and write a format version.
+    fn is_supported_version(version: u8) -> bool {
+        version <= MConfig::LATEST_VERSION
+    }
 
-        // validate lengths; UTF-8 constraint already ensured by String
-        for (key, value) in &value {
-            if key.len() > MConfig::MAX_KEY_LEN {
-                return Err(MCError::KeyTooBig);
+    /// Move entries out of an MConfig whose contents no longer fit, returning them in a new MConfig
+    /// with the same version, algorithm, and secret.
+    /// The largest entries are moved first, so as few entries as possible leave `self`.
+    /// If the contents already fit, the returned MConfig is empty.
+    pub fn split_off_overflow(&mut self) -> MConfig {
+        let mut overflow = MCHashMap::new();
+
+        if self.used_len(None) > self.block_size {
+            let prefix = MConfig::len_prefix_size(self.version);
+            let mut by_size: Vec<(usize, String)> = self
+                .entries
+                .iter()
+                .map(|(k, v)| (MConfig::entry_len(k, v, prefix), k.clone()))
+                .collect();
+            by_size.sort_by(|a, b| b.cmp(a));
+
+            for (_, k) in by_size {
+                if self.used_len(None) <= self.block_size {
+                    break;
+                }
+                if let Some(v) = self.entries.remove(&k) {
+                    overflow.insert(k, v);
+                }
             }
-            total_len += key.len() + 1;
+        }
+
+        MConfig {
+            version: self.version,
+            algorithm: self.algorithm,
+            entries: overflow,
+            secret: self.secret.clone(),
+            sorted: self.sorted,
+            fingerprint: self.fingerprint,
+            kdf: self.kdf,
+            block_size: self.block_size,
+            rotate_after: self.rotate_after,
+        }
+    }
+
+    /// Canonicalize this MConfig so that the same contents always serialize to the same entries region,
+    /// regardless of how the data was laid out when it was loaded. Entries are written sorted by key.
+    /// The random padding still differs between calls to `to_vec`.
+    pub fn normalize(&mut self) {
+        self.sorted = true;
+    }
+
+    /// Change the secret used during obfuscation. Use `None` for no secret: an empty secret would
+    /// obfuscate nothing, so serializing fails with `EmptySecret` until it is replaced.
+    pub fn set_secret(&mut self, secret: Option<String>) {
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut self.secret);
+        self.secret = secret;
+    }
+
+    /// Change the algorithm used for obfuscation when a secret is set.
+    /// Version 0 ignores this and always uses `Algorithm::Xor`.
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.algorithm = algorithm;
+    }
+
+    /// Choose whether `to_vec` stores a fingerprint of the secret in the header, letting a load with
+    /// the wrong secret fail early with `WrongSecret`. This needs a secret and version 1 or later.
+    pub fn set_fingerprint(&mut self, enabled: bool) {
+        self.fingerprint = enabled;
+    }
+
+    /// Derive the obfuscation key from the secret with new KDF parameters from the next `to_vec` on,
+    /// e.g. to raise the iteration count over time without changing the secret.
+    /// The parameters are stored in the header, so loading only needs the secret.
+    /// They are only used with a secret and version 1 or later. Without them, `Algorithm::ChaCha20Poly1305`
+    /// uses `KdfParams::default()` with a new salt on each `to_vec`.
+    pub fn rehash(&mut self, new_params: KdfParams) {
+        self.kdf = Some(new_params);
+    }
+
+    /// Applies the obfuscation algorithm of the header if there is a key, see `Header::key`.
+    /// If the header has room for a fingerprint, it is set from the key under a new random salt first.
+    /// Sealing with `Algorithm::ChaCha20Poly1305` also stores the nonce and tag in the header.
+    /// With `Algorithm::Xor` in version 1 or later, a sentinel derived from the key is prepended first.
+    /// If the header has room for a checksum, it is set to the CRC32 of the plaintext records, with or without a key.
+    fn obfuscate(buffer: Vec<u8>, key: Option<&[u8]>, header: &mut Header) -> Vec<u8> {
+        if let (Some(key), Some(_)) = (key, header.fingerprint) {
+            header.fingerprint = Some(MConfig::key_fingerprint(key, rand::random()));
+        }
+        if header.checksum.is_some() {
+            header.checksum = Some(crc32fast::hash(MConfig::records(&buffer, header.len_prefix_size())));
+        }
+        match (key, header.algorithm) {
+            (Some(key), Algorithm::Xor) => {
+                let buffer = match header.flags & MConfig::SENTINEL_FLAG {
+                    0 => buffer,
+                    _ => [&MConfig::entries_sentinel(key)[..], &buffer].concat(),
+                };
+                MConfig::xor_buffer(buffer, key)
+            }
+            (Some(key), Algorithm::XorValues) => MConfig::xor_values(buffer, key, header.len_prefix_size()),
+            (Some(key), Algorithm::ChaCha20Poly1305) => MConfig::seal(buffer, key, header),
+            _ => buffer,
+        }
+    }
+
+    /// Applies the deobfuscation algorithm of the header if there is a key, see `Header::key`.
+    /// Fails with `WrongSecret` if the sentinel written with `Algorithm::Xor` does not match, which is then
+    /// removed, or with `DecryptionFailed` if `Algorithm::ChaCha20Poly1305` cannot decrypt the entries.
+    /// Fails with `ChecksumMismatch` if the header has a checksum that the result does not match.
+    fn deobfuscate(buffer: Vec<u8>, key: Option<&[u8]>, header: &Header) -> MCResult<Vec<u8>> {
+        let buffer = MConfig::deobfuscate_unchecked(buffer, key, header)?;
+        match header.checksum {
+            Some(checksum) if checksum != crc32fast::hash(MConfig::records(&buffer, header.len_prefix_size())) => {
+                Err(MCError::ChecksumMismatch)
+            }
+            _ => Ok(buffer),
+        }
+    }
+
+    /// Like `deobfuscate`, but without checking the checksum.
+    fn deobfuscate_unchecked(buffer: Vec<u8>, key: Option<&[u8]>, header: &Header) -> MCResult<Vec<u8>> {
+        Ok(match (key, header.algorithm) {
+            (Some(key), Algorithm::Xor) => {
+                let mut buffer = MConfig::xor_buffer(buffer, key);
+                if header.flags & MConfig::SENTINEL_FLAG != 0 {
+                    if buffer.get(..MConfig::SENTINEL_LEN) != Some(&MConfig::entries_sentinel(key)[..]) {
+                        return Err(MCError::WrongSecret);
+                    }
+                    buffer.drain(..MConfig::SENTINEL_LEN);
+                }
+                buffer
+            }
+            (Some(key), Algorithm::XorValues) => MConfig::xor_values(buffer, key, header.len_prefix_size()),
+            (Some(key), Algorithm::ChaCha20Poly1305) => MConfig::unseal(buffer, key, header)?,
+            _ => buffer,
+        })
+    }
+
+    /// The records at the start of a plaintext entries region, up to and including the terminator,
+    /// with lengths of `prefix` bytes. Stops early at a truncated record, which the parser reports.
+    fn records(buf: &[u8], prefix: usize) -> &[u8] {
+        let mut pos = 0;
+        while let Some(key_len) = read_len(buf, pos, prefix) {
+            pos += prefix;
+            if key_len == 0 {
+                break; //end of data
+            }
+            pos += key_len;
+            match read_len(buf, pos, prefix) {
+                Some(val_len) => pos += prefix + val_len,
+                None => break,
+            }
+        }
+        &buf[..pos.min(buf.len())]
+    }
+
+    /// XOR only the value bytes of each record against the secret, leaving keys and lengths readable.
+    /// The secret restarts at the beginning of each value. Since the record structure is left in the clear,
+    /// this is reversible and used for both ob- and deobfuscation. A truncated record ends the walk;
+    /// the parser reports it.
+    fn xor_values(mut buf: Vec<u8>, secret: &[u8], prefix: usize) -> Vec<u8> {
+        let mut pos = 0;
+
+        while let Some(key_len) = read_len(&buf, pos, prefix) {
+            if key_len == 0 {
+                break; //end of data
+            }
+            pos += prefix + key_len;
+
+            let val_len = match read_len(&buf, pos, prefix) {
+                Some(v) => v,
+                None => break,
+            };
+            pos += prefix;
+
+            let end = (pos + val_len).min(buf.len());
+            for (b, s) in buf[pos..end].iter_mut().zip(secret.iter().cycle()) {
+                *b ^= s;
+            }
+            pos = end;
+        }
+
+        buf
+    }
+
+    /// XOR `data` against the repeated bytes of `secret`, exactly as v0 obfuscation does.
+    /// This is stable public API so that other implementations can check they agree byte-for-byte.
+    pub fn xor_with_secret(data: &[u8], secret: &[u8]) -> Vec<u8> {
+        MConfig::xor_buffer(data.to_vec(), secret)
+    }
+
+    /// Strip the header from serialized data and deobfuscate the rest with `secret`, without parsing any records.
+    /// Meant for forensic tooling inspecting damaged data or data of an uncertain version.
+    /// If the header cannot be parsed, the whole input is XORed against the secret as in v0.
+    /// Entries that fail the sentinel check or fail to decrypt are returned as they are. The checksum is not
+    /// checked, since the data may well be damaged.
+    pub fn try_deobfuscate(bytes: &[u8], secret: &str) -> Vec<u8> {
+        match Header::parse(bytes) {
+            Ok(header) => {
+                let key = header.key(Some(secret));
+                let region = &bytes[header.size()..];
+                MConfig::deobfuscate_unchecked(region.to_vec(), key.as_deref(), &header)
+                    .unwrap_or_else(|_| region.to_vec())
+            }
+            Err(_) => MConfig::xor_buffer(bytes.to_vec(), secret.as_bytes()),
+        }
+    }
+
+    /// The algorithm used in v0. This is reversible so it is used for both ob- and deobfuscation.
+    /// This simply XORs the bytes of data against the bytes of the secret.
+    /// In theory, if the secret were longer than MCONFIG_SIZE, the actual obfuscation would be unbreakable if
+    /// only used once (e.g., one-time pad) but the nature of this whole implementation precludes that sort of security.
+    fn xor_buffer(mut buf: Vec<u8>, secret: &[u8]) -> Vec<u8> {
+        for (b, s) in buf.iter_mut().zip(secret.iter().cycle()) {
+            *b ^= s;
+        }
+        buf
+    }
+
+    /// Helper function to get an Iterator
+    pub fn iter(&self) -> MConfigIter<'_> {
+        MConfigIter::new(self)
+    }
 
-            if let Some(v) = value {
-                if v.len() > MConfig::MAX_VALUE_LEN {
-                    return Err(MCError::ValueTooBig);
+    /// Like the `Debug` output, but with the values shown, e.g. for test failures. The secret is still left out.
+    /// Never log this for a config holding sensitive values.
+    pub fn unredacted_debug(&self) -> String {
+        let sorted: BTreeMap<&String, &Option<String>> = self.entries.iter().collect();
+        format!(
+            "MConfig {{ version: {}, len: {}, secret: {}, entries: {:?} }}",
+            self.version,
+            self.entries.len(),
+            self.secret.is_some(),
+            sorted
+        )
+    }
+
+    /// Export the entries as a compact JSON object, sorted by key. Valueless keys map to `null`.
+    pub fn to_json(&self) -> String {
+        let sorted: BTreeMap<&String, &Option<String>> = self.entries.iter().collect();
+        serde_json::to_string(&sorted).expect("string maps always serialize")
+    }
+
+    /// Apply a JSON object as a patch: a key mapped to a string is set to it, a key mapped to `null` is removed,
+    /// and keys not mentioned are left alone. Valueless entries cannot be set this way.
+    /// Nothing is changed if this fails: with `InvalidFormat` if `json` is not an object of strings and nulls,
+    /// or as `try_extend` does if the result would not fit.
+    pub fn apply_json_patch(&mut self, json: &str) -> MCResult<()> {
+        let patch: BTreeMap<String, Option<String>> =
+            serde_json::from_str(json).map_err(|_| MCError::InvalidFormat)?;
+
+        let mut patched = self.entries.clone();
+        for (k, v) in patch {
+            match v {
+                Some(_) => {
+                    self.check_entry(&k, &v)?;
+                    patched.insert(k, v);
                 }
-                else {
-                    total_len += v.len();
+                None => {
+                    patched.remove(&k);
                 }
             }
-            total_len += 1;
-            if total_len > MConfig::MCONFIG_SIZE - MConfig::HEADER_SIZE {
-                return Err(MCError::TooBig);
+        }
+        let used = MConfig::used_len_for(&patched, &self.header(), None);
+        if used > self.block_size {
+            return Err(MCError::TooBig { over_by: used - self.block_size });
+        }
+
+        self.entries = patched;
+        Ok(())
+    }
+
+    /// Compute the length in bytes of `to_json` output without building the string.
+    /// Useful for sizing buffers or enforcing response limits before exporting.
+    pub fn estimate_json_len(&self) -> usize {
+        fn quoted_len(s: &str) -> usize {
+            2 + s
+                .chars()
+                .map(|c| match c {
+                    '"' | '\\' | '\n' | '\r' | '\t' | '\x08' | '\x0c' => 2,
+                    c if (c as u32) < 0x20 => 6,
+                    c => c.len_utf8(),
+                })
+                .sum::<usize>()
+        }
+
+        let entries_len: usize = self
+            .entries
+            .iter()
+            .map(|(k, v)| quoted_len(k) + 1 + v.as_deref().map_or(4, quoted_len))
+            .sum();
+
+        // braces plus commas between entries
+        2 + entries_len + self.entries.len().saturating_sub(1)
+    }
+
+    /// Return owned copies of all entries, sorted by key.
+    /// Useful for comparing contents, since iteration order is otherwise arbitrary.
+    pub fn to_sorted_vec(&self) -> Vec<(String, Option<String>)> {
+        let mut v: Vec<(String, Option<String>)> =
+            self.entries.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        v.sort();
+        v
+    }
+
+    /// Iterate over the keys in arbitrary order.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Iterate over the values in arbitrary order.
+    pub fn values(&self) -> impl Iterator<Item = &Option<String>> {
+        self.entries.values()
+    }
+
+    /// Iterate over the values in arbitrary order, allowing them to be changed in place.
+    /// Nothing is checked while iterating, so a longer value can leave the contents too big to serialize;
+    /// call `validate` afterwards to find out before `to_vec` would panic.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Option<String>> {
+        self.entries.values_mut()
+    }
+
+    /// Return the keys sorted lexicographically, without cloning them or touching the values.
+    pub fn sorted_keys(&self) -> Vec<&String> {
+        let mut keys: Vec<&String> = self.entries.keys().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Fold over the entries in arbitrary order, like `iter().fold` but with the key and value as separate arguments.
+    pub fn fold<B, F: FnMut(B, &str, &Option<String>) -> B>(&self, init: B, mut f: F) -> B {
+        self.entries.iter().fold(init, |acc, (k, v)| f(acc, k, v))
+    }
+
+    /// The size tier of the block `to_vec` writes.
+    pub fn size_class(&self) -> SizeClass {
+        SizeClass::from_size(self.block_size)
+    }
+
+    /// Split the keys into those with a value and those without, in one pass. Each list is sorted.
+    pub fn partition_by_value(&self) -> (Vec<&String>, Vec<&String>) {
+        let (mut valued, mut valueless): (Vec<&String>, Vec<&String>) = (Vec::new(), Vec::new());
+        for (k, v) in &self.entries {
+            match v {
+                Some(_) => valued.push(k),
+                None => valueless.push(k),
             }
         }
+        valued.sort();
+        valueless.sort();
+        (valued, valueless)
+    }
 
-        Ok(MConfig {
-            version: MConfig::LATEST_VERSION,
+    /// Count the keys that start with `prefix`, e.g. `db.` for a namespace.
+    pub fn count_prefix(&self, prefix: &str) -> usize {
+        self.entries.keys().filter(|k| k.starts_with(prefix)).count()
+    }
+
+    /// Iterate over the entries that have a value satisfying `pred`.
+    /// Valueless keys are always skipped.
+    pub fn filter_values<'a, F: Fn(&str) -> bool + 'a>(
+        &'a self,
+        pred: F,
+    ) -> impl Iterator<Item = (&'a String, &'a str)> {
+        self.entries
+            .iter()
+            .filter_map(|(k, v)| v.as_deref().map(|v| (k, v)))
+            .filter(move |(_, v)| pred(v))
+    }
+}
+
+/// A one-line summary for diagnostics. Entries and the secret are never printed.
+impl Display for MConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MConfig {{ version: {}, entries: {}, secret: {} }}",
+            self.version,
+            self.entries.len(),
+            if self.secret.is_some() { "set" } else { "not set" }
+        )
+    }
+}
+
+/// Shows the version, entry count, whether a secret is set and the keys in order, but never the secret
+/// or any value: every value is masked as `***`. Use `unredacted_debug` to see the values.
+impl std::fmt::Debug for MConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let keys: BTreeMap<&String, std::fmt::Arguments> =
+            self.entries.keys().map(|k| (k, format_args!("***"))).collect();
+        f.debug_struct("MConfig")
+            .field("version", &self.version)
+            .field("len", &self.entries.len())
+            .field("secret", &self.secret.is_some())
+            .field("entries", &keys)
+            .finish()
+    }
+}
+
+/// Index notation support
+/// Returns a reference to the value at the key/index.
+/// #Panics
+/// Panics if the key is not present.
+impl Index<&str> for MConfig {
+    type Output = Option<String>;
+
+    fn index(&self, index: &str) -> &Self::Output {
+        &self.entries[index]
+    }
+}
+
+/// Iterator support
+pub struct MConfigIter<'a> {
+    mc_iter: HashMapIter<'a, String, Option<String>>,
+}
+
+impl MConfigIter<'_> {
+    fn new(mconfig: &MConfig) -> MConfigIter<'_> {
+        MConfigIter {
+            mc_iter: mconfig.entries.iter(),
+        }
+    }
+}
+
+impl<'a> Iterator for MConfigIter<'a> {
+    type Item = (&'a String, &'a Option<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.mc_iter.next()
+    }
+}
+
+/// Consume the MConfig, yielding its entries in arbitrary order without copying them.
+impl IntoIterator for MConfig {
+    type Item = (String, Option<String>);
+    type IntoIter = std::collections::hash_map::IntoIter<String, Option<String>>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        std::mem::take(&mut self.entries).into_iter()
+    }
+}
+
+/// Two MConfigs are equal if they hold the same entries. The secret, version, algorithm, block size and other
+/// serialization settings are intentionally ignored, and so is the random padding of serialized data.
+impl PartialEq for MConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries
+    }
+}
+
+/// With the `zeroize` feature the secret is overwritten before its memory is freed.
+/// Copies made by the caller, and keys derived from the secret while serializing, are not covered.
+#[cfg(feature = "zeroize")]
+impl Drop for MConfig {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.secret);
+    }
+}
+
+impl<'a> IntoIterator for &'a MConfig {
+    type Item = (&'a String, &'a Option<String>);
+    type IntoIter = MConfigIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Convert a plain hashmap to an MConfig
+/// This sets up the object with the default version and no secret.
+impl TryFrom<std::collections::HashMap<String, Option<String>>> for MConfig {
+    type Error = MCError;
+
+    fn try_from(value: HashMap<String, Option<String>>) -> Result<Self, Self::Error> {
+        // validate lengths; UTF-8 constraint already ensured by String
+        for (key, value) in &value {
+            MConfig::check_entry_for(MConfig::DEFAULT_VERSION, key, value)?;
+        }
+
+        let mc = MConfig {
+            version: MConfig::DEFAULT_VERSION,
+            algorithm: Algorithm::ChaCha20Poly1305,
             entries: value,
             secret: None,
-        })
+            sorted: false,
+            fingerprint: false,
+            kdf: None,
+            block_size: MConfig::MCONFIG_SIZE,
+            rotate_after: None,
+        };
+
+        // header, entries and the terminating zero byte
+        let used = mc.used_len(None);
+        if used > MConfig::MCONFIG_SIZE {
+            return Err(MCError::TooBig { over_by: used - MConfig::MCONFIG_SIZE });
+        }
+        Ok(mc)
     }
-}
\ No newline at end of file
+}
+
+/// Collect key-value pairs into a new MConfig, see `MConfig::try_from_iter`.
+/// Panics if an entry does not fit; use `MConfig::try_from_iter` to get an error instead.
+impl FromIterator<(String, Option<String>)> for MConfig {
+    fn from_iter<I: IntoIterator<Item = (String, Option<String>)>>(iter: I) -> Self {
+        MConfig::try_from_iter(iter).unwrap_or_else(|e| panic!("entries do not fit in an MConfig: {}", e))
+    }
+}
+
+/// Parse serialized data that has no secret.
+/// This is the same as `MConfig::builder().try_build_ref(value)`.
+impl TryFrom<&[u8]> for MConfig {
+    type Error = MCError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        MConfig::builder().try_build_ref(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    #[test]
+    fn reader_writer_round_trip() {
+        let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        mc.try_insert("Bye".to_string(), None).unwrap();
+
+        let mut cursor = Cursor::new(Vec::new());
+        mc.to_writer(&mut cursor).unwrap();
+        assert_eq!(cursor.get_ref().len(), MConfig::MCONFIG_SIZE);
+
+        cursor.set_position(0);
+        let loaded = MConfig::from_reader(cursor, Some("TACOS")).unwrap();
+        assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+        assert_eq!(loaded.get("Bye"), Some(&None));
+    }
+
+    #[test]
+    fn bytes_key_insert() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        mc.try_insert_bytes_key("Grüße".as_bytes(), Some(b"World")).unwrap();
+        mc.try_insert_bytes_key(b"Bye", None).unwrap();
+
+        assert_eq!(mc.get("Grüße"), Some(&Some("World".to_string())));
+        assert_eq!(mc.get("Bye"), Some(&None));
+    }
+
+    #[test]
+    fn bytes_key_invalid_utf8_fails() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+
+        assert_eq!(mc.try_insert_bytes_key(&[0x48, 0xff, 0xfe], None), Err(MCError::InvalidUTF8));
+        assert_eq!(mc.try_insert_bytes_key(b"Hello", Some(&[0xc3, 0x28])), Err(MCError::InvalidUTF8));
+        assert_eq!(mc.len(), 0);
+    }
+
+    #[test]
+    fn filter_values_by_substring() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        mc.try_insert("db.host".to_string(), Some("prod-db.local".to_string())).unwrap();
+        mc.try_insert("web.host".to_string(), Some("staging-web.local".to_string())).unwrap();
+        mc.try_insert("cache.host".to_string(), Some("prod-cache.local".to_string())).unwrap();
+        mc.try_insert("prod".to_string(), None).unwrap();
+
+        let mut found: Vec<&String> = mc.filter_values(|v| v.contains("prod")).map(|(k, _)| k).collect();
+        found.sort();
+
+        assert_eq!(found, vec!["cache.host", "db.host"]);
+    }
+
+    #[test]
+    fn from_str_pairs_all_valued() {
+        let mc = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], Some("TACOS")).unwrap();
+
+        assert_eq!(mc.len(), 2);
+        assert_eq!(mc.get("Hello"), Some(&Some("World".to_string())));
+        assert_eq!(mc.get("Taco"), Some(&Some("Tuesday".to_string())));
+    }
+
+    #[test]
+    fn sorted_vec_after_round_trip() {
+        let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Taco".to_string(), Some("Tuesday".to_string())).unwrap();
+        mc.try_insert("Bye".to_string(), None).unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+
+        let loaded = MConfig::builder().load(mc.to_vec()).secret("TACOS").try_build().unwrap();
+
+        assert_eq!(
+            loaded.to_sorted_vec(),
+            vec![
+                ("Bye".to_string(), None),
+                ("Hello".to_string(), Some("World".to_string())),
+                ("Taco".to_string(), Some("Tuesday".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn max_value_len_near_capacity() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        // 670 entries of 12 bytes each leaves 145 bytes free
+        for i in 0..670 {
+            mc.try_insert(format!("key{:0>3}", i), Some("1234".to_string())).unwrap();
+        }
+
+        let max = mc.max_value_len_for("last");
+        assert!(max < MConfig::MAX_VALUE_LEN);
+        assert!(mc.try_insert("last".to_string(), Some("x".repeat(max + 1))).is_err());
+        mc.try_insert("last".to_string(), Some("x".repeat(max))).unwrap();
+
+        // existing keys may grow into their own space
+        let max = mc.max_value_len_for("key000");
+        assert_eq!(max, 4);
+        assert!(mc.try_insert("key000".to_string(), Some("x".repeat(max + 1))).is_err());
+        mc.try_insert("key000".to_string(), Some("x".repeat(max))).unwrap();
+        assert_eq!(mc.to_vec().len(), MConfig::MCONFIG_SIZE);
+
+        assert_eq!(MConfig::builder().try_build().unwrap().max_value_len_for("k"), MConfig::MAX_VALUE_LEN);
+        assert_eq!(mc.max_value_len_for(""), 0);
+        assert_eq!(mc.max_value_len_for(&"k".repeat(MConfig::MAX_KEY_LEN + 1)), 0);
+    }
+
+    #[test]
+    fn merge_files_overlapping_keys() {
+        let mut a = MConfig::from_str_pairs(&[("shared", "a"), ("only_a", "1")], Some("TACOS")).unwrap();
+        a.rehash(KdfParams::new(16));
+        let a = a.to_vec();
+        let mut b = MConfig::from_str_pairs(&[("shared", "b"), ("only_b", "2")], Some("TACOS")).unwrap();
+        b.rehash(KdfParams::new(16));
+        let b = b.to_vec();
+
+        let merged = MConfig::merge_files(&[&a, &b], Some("TACOS"), DuplicatePolicy::LastWins).unwrap();
+        assert_eq!(
+            merged.to_sorted_vec(),
+            vec![
+                ("only_a".to_string(), Some("1".to_string())),
+                ("only_b".to_string(), Some("2".to_string())),
+                ("shared".to_string(), Some("b".to_string())),
+            ]
+        );
+
+        let merged = MConfig::merge_files(&[&a, &b], Some("TACOS"), DuplicatePolicy::FirstWins).unwrap();
+        assert_eq!(merged.get("shared"), Some(&Some("a".to_string())));
+
+        assert_eq!(
+            MConfig::merge_files(&[&a, &b], Some("TACOS"), DuplicatePolicy::Error).err(),
+            Some(MCError::DuplicateKey)
+        );
+    }
+
+    #[test]
+    fn xor_with_secret_known_bytes() {
+        let data = [0x00, 0x01, 0x02, 0x03, 0xff];
+        let secret = [0x0f, 0xf0];
+
+        let xored = MConfig::xor_with_secret(&data, &secret);
+        assert_eq!(xored, vec![0x0f, 0xf1, 0x0d, 0xf3, 0xf0]);
+        assert_eq!(MConfig::xor_with_secret(&xored, &secret), data.to_vec());
+    }
+
+    #[test]
+    fn secure_remove_reports_presence() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        mc.try_insert("password".to_string(), Some("hunter2".to_string())).unwrap();
+        mc.try_insert("flag".to_string(), None).unwrap();
+
+        assert!(mc.secure_remove("password"));
+        assert!(!mc.contains_key("password"));
+        assert!(!mc.secure_remove("password"));
+        assert!(mc.secure_remove("flag"));
+        assert_eq!(mc.len(), 0);
+    }
+
+    #[test]
+    fn json_len_estimate() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        assert_eq!(mc.estimate_json_len(), mc.to_json().len());
+
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        mc.try_insert("Bye".to_string(), None).unwrap();
+        mc.try_insert("quote\"d".to_string(), Some("tab\tnew\nline\u{1}ü".to_string())).unwrap();
+
+        let json = mc.to_json();
+        assert!(json.starts_with(r#"{"Bye":null,"Hello":"World","#));
+        assert_eq!(mc.estimate_json_len(), json.len());
+    }
+
+    #[test]
+    fn blob_with_entry_adds_key() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        let raw = mc.to_vec();
+
+        let updated = MConfig::blob_with_entry(&raw, Some("TACOS"), "Taco", Some("Tuesday")).unwrap();
+        let mc = MConfig::builder().load(updated).secret("TACOS").try_build().unwrap();
+        assert_eq!(mc.get("Hello"), Some(&Some("World".to_string())));
+        assert_eq!(mc.get("Taco"), Some(&Some("Tuesday".to_string())));
+
+        let too_long = "v".repeat(MConfig::MAX_VALUE_LEN + 1);
+        assert_eq!(
+            MConfig::blob_with_entry(&raw, Some("TACOS"), "Taco", Some(&too_long)),
+            Err(MCError::ValueTooBig)
+        );
+    }
+
+    #[test]
+    fn normalize_gives_stable_layout() {
+        // v0 blocks without a secret holding the same entries in different orders
+        let mut a = MConfig::MAGIC_HEADER_BYTES.to_vec();
+        a.extend_from_slice(&[0, 1, b'a', 1, b'1', 1, b'b', 0, 0]);
+        let mut b = MConfig::MAGIC_HEADER_BYTES.to_vec();
+        b.extend_from_slice(&[0, 1, b'b', 0, 1, b'a', 1, b'1', 0]);
+
+        let mut mc_a = MConfig::builder().load(a).try_build().unwrap();
+        let mut mc_b = MConfig::builder().load(b).try_build().unwrap();
+        mc_a.normalize();
+        mc_b.normalize();
+
+        let used = mc_a.used_len(None);
+        assert_eq!(used, mc_b.used_len(None));
+        assert_eq!(mc_a.to_vec()[..used], mc_b.to_vec()[..used]);
+        assert_eq!(mc_a.to_vec()[MConfig::HEADER_SIZE..used], [1, b'a', 1, b'1', 1, b'b', 0, 0]);
+    }
+
+    #[test]
+    fn split_off_overflow_moves_largest() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        for i in 0..600 {
+            mc.try_insert(format!("key{:0>3}", i), Some("1234".to_string())).unwrap();
+        }
+        // bypass the size checks to get an over-capacity config
+        for i in 0..5 {
+            mc.entries.insert(format!("big{}", i), Some("x".repeat(200)));
+        }
+        assert!(mc.used_len(None) > MConfig::MCONFIG_SIZE);
+
+        let overflow = mc.split_off_overflow();
+        assert!(mc.used_len(None) <= MConfig::MCONFIG_SIZE);
+        assert_eq!(mc.len() + overflow.len(), 605);
+        assert!(overflow.iter().all(|(k, _)| k.starts_with("big")));
+        assert_eq!(mc.to_vec().len(), MConfig::MCONFIG_SIZE);
+
+        assert_eq!(mc.split_off_overflow().len(), 0);
+    }
+
+    #[test]
+    fn try_from_plain_bytes() {
+        let bytes = MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap().to_vec();
+
+        let mc = MConfig::try_from(&bytes[..]).unwrap();
+        assert_eq!(mc.get("Hello"), Some(&Some("World".to_string())));
+        assert_eq!(MConfig::try_from(&bytes[..4]).err(), Some(MCError::TooShort));
+    }
+
+    #[test]
+    fn get_ignore_case_lookup() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        mc.try_insert("ApiKey".to_string(), Some("abc123".to_string())).unwrap();
+
+        assert_eq!(mc.get("apikey"), None);
+        assert_eq!(mc.get_ignore_case("apikey"), Some(&Some("abc123".to_string())));
+        assert_eq!(mc.get_ignore_case("APIKEY"), Some(&Some("abc123".to_string())));
+        assert_eq!(mc.get_ignore_case("api_key"), None);
+        assert!(mc.contains_key("ApiKey"));
+    }
+
+    #[test]
+    fn clear_value_keeps_key() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+
+        assert_eq!(mc.clear_value("Hello"), Ok(true));
+        assert_eq!(mc.get("Hello"), Some(&None));
+        assert_eq!(mc.clear_value("Hello"), Ok(false));
+        assert_eq!(mc.clear_value("Missing"), Err(MCError::MissingKey));
+        assert_eq!(mc.len(), 1);
+    }
+
+    #[test]
+    fn xor_values_round_trip() {
+        let mut mc = MConfig::builder()
+            .secret("TACOS")
+            .algorithm(Algorithm::XorValues)
+            .try_build()
+            .unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        mc.try_insert("Bye".to_string(), None).unwrap();
+
+        let mcv = mc.to_vec();
+        let loaded = MConfig::builder().load(mcv).secret("TACOS").try_build().unwrap();
+
+        assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+        assert_eq!(loaded.get("Bye"), Some(&None));
+    }
+
+    #[test]
+    fn xor_values_leaves_keys_readable() {
+        let mut mc = MConfig::builder()
+            .secret("TACOS")
+            .algorithm(Algorithm::XorValues)
+            .try_build()
+            .unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+
+        let mcv = mc.to_vec();
+        let contains = |needle: &[u8]| mcv.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"\x05Hello\x05"));
+        assert!(!contains(b"World"));
+        assert_eq!(MConfig::blob_contains_key(&mcv, None, "Hello"), Ok(true));
+    }
+
+    #[test]
+    fn display_is_redacted() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], Some("TACOS")).unwrap();
+        mc.try_insert("Bye".to_string(), None).unwrap();
+
+        let shown = mc.to_string();
+        assert_eq!(shown, "MConfig { version: 1, entries: 3, secret: set }");
+        assert!(!shown.contains("World") && !shown.contains("TACOS"));
+
+        let plain = MConfig::builder().try_build().unwrap();
+        assert_eq!(plain.to_string(), "MConfig { version: 1, entries: 0, secret: not set }");
+    }
+
+    #[test]
+    fn debug_is_redacted() {
+        let mut mc = MConfig::from_str_pairs(&[("Taco", "Tuesday"), ("Hello", "World")], Some("TACOS")).unwrap();
+        mc.try_insert("Bye", None).unwrap();
+
+        let shown = format!("{mc:?}");
+        assert_eq!(
+            shown,
+            r#"MConfig { version: 1, len: 3, secret: true, entries: {"Bye": ***, "Hello": ***, "Taco": ***} }"#
+        );
+        assert!(!shown.contains("World") && !shown.contains("Tuesday") && !shown.contains("TACOS"));
+        assert!(format!("{mc:#?}").contains("\"Hello\": ***"));
+
+        let full = mc.unredacted_debug();
+        assert_eq!(
+            full,
+            r#"MConfig { version: 1, len: 3, secret: true, entries: {"Bye": None, "Hello": Some("World"), "Taco": Some("Tuesday")} }"#
+        );
+        assert!(!full.contains("TACOS"));
+    }
+
+    #[test]
+    fn length_limits_per_version() {
+        let mut mc = MConfig::builder().version(0).try_build().unwrap();
+        assert_eq!((mc.max_key_len(), mc.max_value_len()), (255, 255));
+
+        mc.set_version(1).unwrap();
+        assert_eq!((mc.max_key_len(), mc.max_value_len()), (255, 255));
+        assert_eq!(mc.try_insert("k".repeat(mc.max_key_len() + 1), None), Err(MCError::KeyTooBig));
+        let v1_limits = (mc.max_key_len(), mc.max_value_len());
+
+        // two-byte lengths raise both limits
+        mc.set_version(2).unwrap();
+        assert_eq!((mc.max_key_len(), mc.max_value_len()), (65_535, 65_535));
+        assert_ne!((mc.max_key_len(), mc.max_value_len()), v1_limits);
+        assert_eq!(mc.try_insert("k".repeat(256), None), Ok(None));
+    }
+
+    #[test]
+    fn sorted_keys_out_of_order_inserts() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        for key in ["delta", "alpha", "Charlie", "bravo", "alpha2"] {
+            mc.try_insert(key.to_string(), None).unwrap();
+        }
+
+        assert_eq!(mc.sorted_keys(), vec!["Charlie", "alpha", "alpha2", "bravo", "delta"]);
+    }
+
+    #[test]
+    fn entropy_plain_vs_obfuscated() {
+        let pairs: Vec<(String, String)> = (0..20).map(|i| (format!("key{i}"), format!("value number {i}"))).collect();
+        let pairs: Vec<(&str, &str)> = pairs.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        let plain = MConfig::from_str_pairs(&pairs, None).unwrap().to_vec();
+        let mut xored = MConfig::from_str_pairs(&pairs, Some("a reasonably long secret, 42!")).unwrap();
+        xored.rehash(KdfParams::new(16));
+        let xored = xored.to_vec();
+
+        assert!(MConfig::blob_entropy(&plain) < MConfig::blob_entropy(&xored));
+        assert!(!MConfig::is_probably_obfuscated(&plain));
+        assert!(MConfig::is_probably_obfuscated(&xored));
+    }
+
+    #[test]
+    fn get_or_insert_with_runs_once() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        let mut calls = 0;
+
+        for _ in 0..2 {
+            let v = mc.get_or_insert_with("token".to_string(), || {
+                calls += 1;
+                Some("generated".to_string())
+            });
+            assert_eq!(v, Ok(&Some("generated".to_string())));
+        }
+
+        assert_eq!(calls, 1);
+        assert_eq!(
+            mc.get_or_insert_with("big".to_string(), || Some("v".repeat(MConfig::MAX_VALUE_LEN + 1))),
+            Err(MCError::ValueTooBig)
+        );
+        assert!(!mc.contains_key("big"));
+    }
+
+    #[test]
+    fn add_overwrites() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+
+        mc.add("Key", Some("first")).unwrap();
+        mc.add("Key", Some("second")).unwrap();
+        assert_eq!(mc.get("Key"), Some(&Some("second".to_string())));
+
+        mc.add("Key", None).unwrap();
+        assert_eq!(mc.get("Key"), Some(&None));
+        assert_eq!(mc.len(), 1);
+    }
+
+    #[test]
+    fn set_index_then_index() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+
+        mc.set_index("Key", Some("Value")).unwrap();
+        assert_eq!(mc["Key"], Some("Value".to_string()));
+
+        mc.set_index("Key", None).unwrap();
+        assert_eq!(mc["Key"], None);
+    }
+
+    #[test]
+    fn over_limit_serializes_to_error() {
+        let mut mc = MConfig::builder().secret("secret").kdf(KdfParams::new(16)).try_build().unwrap();
+        let max = mc.max_value_len();
+        let mut i = 0;
+        while mc.try_insert(format!("k{i}"), Some("v".repeat(max))).is_ok() {
+            i += 1;
+        }
+        let filler = mc.max_value_len_for("filler");
+        mc.try_insert("filler".to_string(), Some("v".repeat(filler))).unwrap();
+        assert!(mc.try_to_vec().is_ok());
+
+        // the fingerprint grows the header past the space left
+        mc.set_fingerprint(true);
+        assert_eq!(mc.try_to_vec().err(), Some(MCError::TooBig { over_by: MConfig::FINGERPRINT_LEN }));
+        assert_eq!(
+            mc.to_writer(Cursor::new(Vec::new())).err().map(|e| e.kind()),
+            Some(std::io::ErrorKind::InvalidInput)
+        );
+
+        mc.entries.insert("big".to_string(), Some("v".repeat(max + 1)));
+        assert_eq!(mc.try_to_vec().err(), Some(MCError::ValueTooBig));
+    }
+
+    #[test]
+    fn count_prefix_per_namespace() {
+        let mc = MConfig::from_str_pairs(
+            &[("db.host", "h"), ("db.port", "1"), ("db.user", "u"), ("web.port", "2"), ("debug", "0")],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(mc.count_prefix("db."), 3);
+        assert_eq!(mc.count_prefix("web."), 1);
+        assert_eq!(mc.count_prefix("d"), 4);
+        assert_eq!(mc.count_prefix("cache."), 0);
+        assert_eq!(mc.count_prefix(""), 5);
+    }
+
+    #[test]
+    fn try_deobfuscate_known_entries() {
+        let plain = [3, b'K', b'e', b'y', 5, b'V', b'a', b'l', b'u', b'e', 0];
+
+        for version in [0, 1] {
+            let mut mc = MConfig::builder().secret("forensics").kdf(KdfParams::new(16)).version(version).try_build().unwrap();
+            mc.try_insert("Key".to_string(), Some("Value".to_string())).unwrap();
+            let bytes = mc.to_vec();
+
+            let entries = MConfig::try_deobfuscate(&bytes, "forensics");
+            assert_eq!(entries.len(), MConfig::MCONFIG_SIZE - mc.header().size());
+            assert_eq!(entries[..plain.len()], plain);
+        }
+    }
+
+    #[test]
+    fn too_big_reports_overflow() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        let max = mc.max_value_len();
+        let mut i = 0;
+        while mc.try_insert(format!("k{i}"), Some("v".repeat(max))).is_ok() {
+            i += 1;
+        }
+        let filler = mc.max_value_len_for("filler");
+        mc.try_insert("filler".to_string(), Some("v".repeat(filler))).unwrap();
+
+        // 1 + 1 key bytes and 1 + 2 value bytes
+        assert_eq!(
+            mc.try_insert("k".to_string(), Some("12".to_string())),
+            Err(MCError::TooBig { over_by: 5 })
+        );
+    }
+
+    #[test]
+    fn with_entries_serializes_sorted() {
+        let entries = BTreeMap::from([
+            ("b".to_string(), None),
+            ("a".to_string(), Some("1".to_string())),
+            ("c".to_string(), Some("3".to_string())),
+        ]);
+
+        let mut mc = MConfig::with_entries(entries, Some("TACOS".to_string())).unwrap();
+        mc.rehash(KdfParams::new(16));
+        let region = MConfig::try_deobfuscate(&mc.to_vec(), "TACOS");
+
+        assert_eq!(region[..12], [1, b'a', 1, b'1', 1, b'b', 0, 1, b'c', 1, b'3', 0]);
+        assert_eq!(
+            MConfig::with_entries(BTreeMap::from([("k".repeat(256), None)]), None).err(),
+            Some(MCError::KeyTooBig)
+        );
+    }
+
+    #[test]
+    fn serialize_verified_round_trip() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+
+        let bytes = mc.serialize_verified().unwrap();
+
+        let loaded = MConfig::builder().secret("TACOS").load(bytes).try_build().unwrap();
+        assert_eq!(loaded.to_sorted_vec(), mc.to_sorted_vec());
+    }
+
+    #[test]
+    fn serialize_verified_catches_corruption() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+
+        // stand-in for a faulty serializer: flip a bit of the stored key
+        let mut bytes = mc.to_vec();
+        bytes[MConfig::V1_HEADER_SIZE + 1] ^= 0x20;
+        assert_eq!(mc.verify_serialized(bytes).err(), Some(MCError::IntegrityFailure));
+
+        // and one that produces data that does not parse at all
+        let mut bytes = mc.to_vec();
+        bytes[0] = 0;
+        assert_eq!(mc.verify_serialized(bytes).err(), Some(MCError::IntegrityFailure));
+    }
+
+    #[test]
+    fn fold_sums_value_bytes() {
+        let mut mc = MConfig::from_str_pairs(&[("a", "12"), ("b", "345"), ("c", "ü")], None).unwrap();
+        mc.try_insert("d".to_string(), None).unwrap();
+
+        let total = mc.fold(0, |acc, _, v| acc + v.as_ref().map_or(0, |v| v.len()));
+
+        assert_eq!(total, 7);
+    }
+
+    /// Entries that fill a latest-version block exactly, terminator included, plus `extra` value bytes.
+    fn boundary_entries(extra: usize) -> HashMap<String, Option<String>> {
+        // 31 entries of 3 + 1 + 255 + 1 = 260 bytes, then 119 bytes, after the 12-byte header with its
+        // checksum and the terminator
+        let mut entries: HashMap<String, Option<String>> =
+            (0..31).map(|i| (format!("k{i:02}"), Some("v".repeat(255)))).collect();
+        entries.insert("x".to_string(), Some("v".repeat(116 + extra)));
+        entries
+    }
+
+    #[test]
+    fn try_from_hashmap_counts_terminator() {
+        let mc = MConfig::try_from(boundary_entries(0)).unwrap();
+        assert_eq!(mc.used_len(None), MConfig::MCONFIG_SIZE);
+        assert_eq!(mc.to_vec().len(), MConfig::MCONFIG_SIZE);
+
+        assert_eq!(MConfig::try_from(boundary_entries(1)).err(), Some(MCError::TooBig { over_by: 1 }));
+    }
+
+    #[test]
+    fn try_insert_up_to_boundary() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        for (k, v) in boundary_entries(0) {
+            mc.try_insert(k, v).unwrap();
+        }
+        assert_eq!(mc.to_vec().len(), MConfig::MCONFIG_SIZE);
+
+        assert_eq!(
+            mc.try_insert("x".to_string(), Some("v".repeat(117))),
+            Err(MCError::TooBig { over_by: 1 })
+        );
+        assert_eq!(mc.try_insert("y".to_string(), None), Err(MCError::TooBig { over_by: 3 }));
+        assert_eq!(mc.to_vec().len(), MConfig::MCONFIG_SIZE);
+    }
+
+    #[test]
+    fn size_class_tiers() {
+        assert_eq!(MConfig::builder().try_build().unwrap().size_class(), SizeClass::Default8K);
+
+        assert_eq!(SizeClass::from_size(1_024), SizeClass::Tiny1K);
+        assert_eq!(SizeClass::from_size(16_384).size(), 16_384);
+        assert_eq!(SizeClass::from_size(5_000), SizeClass::Custom(5_000));
+        assert_eq!(SizeClass::Custom(5_000).size(), 5_000);
+    }
+
+    #[test]
+    fn keys_collects_all() {
+        let mc = MConfig::from_str_pairs(&[("a", "1"), ("b", "2"), ("c", "3")], None).unwrap();
+
+        let keys: HashSet<&String> = mc.keys().collect();
+
+        assert_eq!(keys, HashSet::from([&"a".to_string(), &"b".to_string(), &"c".to_string()]));
+        assert_eq!(mc.keys().filter(|k| k.as_str() > "a").count(), 2);
+    }
+
+    #[test]
+    fn rehash_records_new_params() {
+        let mut mc = MConfig::builder().secret("passphrase").kdf(KdfParams::new(100)).try_build().unwrap();
+        mc.try_insert("Key".to_string(), Some("Value".to_string())).unwrap();
+        let bytes = mc.to_vec();
+        assert_eq!(Header::parse(&bytes).unwrap().kdf.map(|k| k.iterations()), Some(100));
+
+        let mut loaded = MConfig::builder().secret("passphrase").load(bytes).try_build().unwrap();
+        loaded.rehash(KdfParams::new(200));
+        let bytes = loaded.to_vec();
+        assert_eq!(Header::parse(&bytes).unwrap().kdf.map(|k| k.iterations()), Some(200));
+
+        let reloaded = MConfig::builder().secret("passphrase").load(bytes).try_build().unwrap();
+        assert_eq!(reloaded.get("Key"), Some(&Some("Value".to_string())));
+        assert_eq!(reloaded.kdf.map(|k| k.iterations()), Some(200));
+    }
+
+    #[test]
+    fn values_valued_and_valueless() {
+        let mut mc = MConfig::from_str_pairs(&[("a", "1"), ("b", "2"), ("c", "3")], None).unwrap();
+        mc.try_insert("d".to_string(), None).unwrap();
+        mc.try_insert("e".to_string(), None).unwrap();
+
+        assert_eq!(mc.values().filter(|v| v.is_some()).count(), 3);
+        assert_eq!(mc.values().filter(|v| v.is_none()).count(), 2);
+    }
+
+    #[test]
+    fn empty_blob_loads_empty() {
+        let plain = MConfig::empty_blob(None);
+        assert_eq!(plain.len(), MConfig::MCONFIG_SIZE);
+        assert_eq!(MConfig::try_from(plain.as_slice()).unwrap().len(), 0);
+
+        let obfuscated = MConfig::empty_blob(Some("TACOS"));
+        let mc = MConfig::builder().secret("TACOS").load(obfuscated).try_build().unwrap();
+        assert_eq!(mc.len(), 0);
+    }
+
+    #[test]
+    fn into_iter_borrowed_and_owned() {
+        let mc = MConfig::from_str_pairs(&[("a", "1"), ("b", "2")], None).unwrap();
+
+        let mut borrowed = Vec::new();
+        for (k, v) in &mc {
+            borrowed.push((k.clone(), v.clone()));
+        }
+        borrowed.sort();
+        assert_eq!(borrowed, mc.to_sorted_vec());
+
+        let mut owned: Vec<(String, Option<String>)> = Vec::new();
+        for (k, v) in mc {
+            owned.push((k, v));
+        }
+        owned.sort();
+        assert_eq!(owned, borrowed);
+    }
+
+    #[test]
+    fn partition_by_value_mixed() {
+        let mut mc = MConfig::from_str_pairs(&[("c", "3"), ("a", "1")], None).unwrap();
+        mc.try_insert("d".to_string(), None).unwrap();
+        mc.try_insert("b".to_string(), None).unwrap();
+
+        let (valued, valueless) = mc.partition_by_value();
+        assert_eq!(valued, vec!["a", "c"]);
+        assert_eq!(valueless, vec!["b", "d"]);
+    }
+
+    #[test]
+    fn clear_then_reload() {
+        let mut mc = MConfig::builder().secret("TACOS").version(0).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+
+        mc.clear();
+        assert_eq!(mc.len(), 0);
+
+        let bytes = mc.to_vec();
+        assert_eq!(bytes.len(), MConfig::MCONFIG_SIZE);
+        let loaded = MConfig::builder().secret("TACOS").load(bytes).try_build().unwrap();
+        assert_eq!(loaded.len(), 0);
+        assert_eq!(loaded.version, 0);
+    }
+
+    #[test]
+    fn is_empty_tracks_entries() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        assert!(mc.is_empty());
+
+        mc.try_insert("Key".to_string(), None).unwrap();
+        assert!(!mc.is_empty());
+
+        mc.remove("Key");
+        assert!(mc.is_empty());
+    }
+
+    #[test]
+    fn get_mut_and_try_set_value() {
+        let mut mc = MConfig::from_str_pairs(&[("Key", "Value")], None).unwrap();
+
+        if let Some(v) = mc.get_mut("Key") {
+            v.as_mut().unwrap().push_str(" appended");
+        }
+        assert_eq!(mc.get("Key"), Some(&Some("Value appended".to_string())));
+
+        assert_eq!(mc.try_set_value("Key", Some("new".to_string())), Ok(Some("Value appended".to_string())));
+        assert_eq!(mc.try_set_value("Key", Some("v".repeat(256))), Err(MCError::ValueTooBig));
+        assert_eq!(mc.try_set_value("Other", None), Err(MCError::MissingKey));
+        assert_eq!(mc.get("Key"), Some(&Some("new".to_string())));
+
+        *mc.get_mut("Key").unwrap() = Some("v".repeat(256));
+        assert_eq!(mc.try_to_vec().err(), Some(MCError::ValueTooBig));
+    }
+
+    #[test]
+    fn read_header_without_secret() {
+        let mut mc = MConfig::builder()
+            .secret("TACOS")
+            .fingerprint(true)
+            .kdf(KdfParams::new(16))
+            .block_size(1_024)
+            .try_build()
+            .unwrap();
+        mc.set_rotate_after(1_800_000_000);
+
+        let header = MConfig::read_header(&mc.to_vec()).unwrap();
+
+        assert_eq!(header.version(), MConfig::DEFAULT_VERSION);
+        assert_eq!(header.algorithm(), Algorithm::ChaCha20Poly1305);
+        assert!(header.check_key(header.key(Some("TACOS")).as_deref()).is_ok());
+        assert_eq!(header.kdf().map(|k| k.iterations()), Some(16));
+        assert_eq!(header.block_size(), 1_024);
+        assert_eq!(header.rotate_after(), Some(1_800_000_000));
+        assert_eq!(
+            header.flags(),
+            MConfig::FINGERPRINT_FLAG
+                | MConfig::KDF_FLAG
+                | MConfig::BLOCK_SIZE_FLAG
+                | MConfig::ROTATE_AFTER_FLAG
+                | MConfig::AEAD_FLAG
+                | MConfig::CHECKSUM_FLAG
+        );
+    }
+
+    #[test]
+    fn blobs_equal_ignores_padding() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        let a = mc.to_vec();
+        let b = mc.to_vec();
+        assert_ne!(a, b);
+        assert_eq!(MConfig::blobs_equal(&a, &b, Some("TACOS")), Ok(true));
+
+        let mut other = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        other.rehash(KdfParams::new(16));
+        assert_eq!(MConfig::blobs_equal(&a, &other.to_vec(), Some("TACOS")), Ok(false));
+        assert_eq!(MConfig::blobs_equal(&a, &a[..4], Some("TACOS")), Err(MCError::TooShort));
+    }
+
+    #[test]
+    fn from_lines_limited_counts() {
+        let mut lines = String::new();
+        for i in 0..100 {
+            lines.push_str(&format!("key{i:02}={}\n", "v".repeat(100)));
+        }
+        lines.push_str("\nlast\n");
+
+        // 1 + 5 key bytes and 1 + 100 value bytes per entry, plus the header and the terminator
+        let fit = (MConfig::MCONFIG_SIZE - MConfig::V1_HEADER_SIZE - 1) / 107;
+
+        let (mc, imported, skipped) = MConfig::from_lines_limited(lines.as_bytes(), None, true).unwrap();
+        assert_eq!((mc.len(), imported, skipped), (fit, fit, 1));
+        assert!(!mc.contains_key("last"));
+
+        let (mc, imported, skipped) = MConfig::from_lines_limited(lines.as_bytes(), None, false).unwrap();
+        assert_eq!((imported, skipped), (fit + 1, 100 - fit));
+        assert_eq!(mc["last"], None);
+
+        let long = format!("{}=v", "k".repeat(256));
+        let result = MConfig::from_lines_limited(long.as_bytes(), None, false);
+        assert_eq!(result.err(), Some(MCError::KeyTooBig));
+    }
+
+    #[test]
+    fn canonical_bytes_ignore_version() {
+        let pairs = [("b", "2"), ("a", "1"), ("c", "3")];
+        let v1 = MConfig::from_str_pairs(&pairs, Some("TACOS")).unwrap();
+        let mut v0 = MConfig::from_str_pairs(&pairs[..2], None).unwrap();
+        v0.set_version(0).unwrap();
+        v0.try_insert("c".to_string(), Some("3".to_string())).unwrap();
+
+        assert_eq!(v1.canonical_bytes(), v0.canonical_bytes());
+        assert_eq!(
+            v1.canonical_bytes(),
+            [1, 0, b'a', 1, 0, b'1', 1, 0, b'b', 1, 0, b'2', 1, 0, b'c', 1, 0, b'3', 0, 0]
+        );
+    }
+
+    #[test]
+    fn checksum_detects_flipped_byte() {
+        for secret in [None, Some("TACOS")] {
+            let mut mc = MConfig::builder().optional_secret(secret).algorithm(Algorithm::Xor).try_build().unwrap();
+            mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+            let bytes = mc.to_vec();
+            let header = MConfig::read_header(&bytes).unwrap();
+            assert!(header.checksum().is_some());
+
+            let loaded = MConfig::builder().optional_secret(secret).try_build_ref(&bytes).unwrap();
+            assert_eq!(loaded["Hello"], Some("World".to_string()));
+
+            // the last byte of "World"
+            let mut flipped = bytes.clone();
+            flipped[header.reserved_len() + 11] ^= 0x01;
+            let result = MConfig::builder().optional_secret(secret).try_build_ref(&flipped);
+            assert_eq!(result.err(), Some(MCError::ChecksumMismatch));
+
+            // the padding is not covered
+            let mut flipped = bytes.clone();
+            flipped[MConfig::MCONFIG_SIZE - 1] ^= 0x01;
+            assert!(MConfig::builder().optional_secret(secret).try_build_ref(&flipped).is_ok());
+        }
+    }
+
+    #[test]
+    fn load_verified_checks_fingerprint() {
+        let mut mc = MConfig::builder()
+            .secret("TACOS")
+            .fingerprint(true)
+            .algorithm(Algorithm::Plain)
+            .try_build()
+            .unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        let bytes = mc.to_vec();
+
+        assert_eq!(MConfig::load_verified(&bytes, "TACOS").unwrap()["Hello"], Some("World".to_string()));
+        // the entries are stored in the clear, so only the fingerprint can reject the secret
+        assert_eq!(MConfig::load_verified(&bytes, "tacos").err(), Some(MCError::WrongSecret));
+
+        mc.set_fingerprint(false);
+        let bytes = mc.to_vec();
+        assert!(MConfig::read_header(&bytes).unwrap().fingerprint().is_none());
+        assert_eq!(MConfig::load_verified(&bytes, "tacos").unwrap()["Hello"], Some("World".to_string()));
+    }
+
+    #[test]
+    fn try_insert_str_literals() {
+        let mut mc = MConfig::builder().try_build().unwrap();
+        assert_eq!(mc.insert_str("Hello", Some("World")), Ok(None));
+        assert_eq!(mc.insert_str("Hello", Some("There")), Ok(Some("World".to_string())));
+        assert_eq!(mc.try_insert("Bye", None), Ok(None));
+        assert_eq!(mc.try_insert(String::from("Taco"), Some(String::from("Tuesday"))), Ok(None));
+
+        assert_eq!(mc["Hello"], Some("There".to_string()));
+        assert_eq!(mc["Bye"], None);
+        assert_eq!(mc.len(), 3);
+    }
+
+    #[test]
+    fn collect_from_iter() {
+        let pairs = vec![
+            ("Hello".to_string(), Some("World".to_string())),
+            ("Bye".to_string(), None),
+        ];
+        let mc: MConfig = pairs.clone().into_iter().collect();
+        assert_eq!(mc.to_sorted_vec(), MConfig::try_from_iter(pairs).unwrap().to_sorted_vec());
+        assert_eq!(mc["Hello"], Some("World".to_string()));
+        assert_eq!(mc["Bye"], None);
+
+        let oversized = (0..40).map(|i| (format!("k{i:02}"), Some("v".repeat(255))));
+        assert!(matches!(MConfig::try_from_iter(oversized), Err(MCError::TooBig { .. })));
+    }
+
+    #[test]
+    #[should_panic]
+    fn collect_oversized_panics() {
+        let _: MConfig = (0..40).map(|i| (format!("k{i:02}"), Some("v".repeat(255)))).collect();
+    }
+
+    #[test]
+    fn values_mut_then_validate() {
+        let mut mc = MConfig::from_str_pairs(&[("a", "one"), ("b", "two")], None).unwrap();
+        mc.try_insert("c", None).unwrap();
+
+        for v in mc.values_mut() {
+            *v = v.as_ref().map(|v| v.to_uppercase());
+        }
+        assert_eq!(mc.validate(), Ok(()));
+        assert_eq!(mc["a"], Some("ONE".to_string()));
+        assert_eq!(mc["c"], None);
+
+        for v in mc.values_mut() {
+            *v = Some("v".repeat(256));
+        }
+        assert_eq!(mc.validate(), Err(MCError::ValueTooBig));
+    }
+
+    #[test]
+    fn try_extend_is_atomic() {
+        let mut mc = MConfig::from_str_pairs(&[("a", "1")], None).unwrap();
+        mc.try_extend([("a".to_string(), Some("2".to_string())), ("b".to_string(), None)])
+            .unwrap();
+        assert_eq!(mc.to_sorted_vec(), [("a".to_string(), Some("2".to_string())), ("b".to_string(), None)]);
+
+        // each entry fits on its own, but not all of them together
+        let oversized = (0..40).map(|i| (format!("k{i:02}"), Some("v".repeat(255))));
+        assert!(matches!(mc.try_extend(oversized), Err(MCError::TooBig { .. })));
+        assert_eq!(mc.len(), 2);
+
+        let too_long = [("c".to_string(), None), ("k".repeat(256), None)];
+        assert_eq!(mc.try_extend(too_long), Err(MCError::KeyTooBig));
+        assert!(!mc.contains_key("c"));
+    }
+
+    #[test]
+    fn apply_json_patch_sets_and_deletes() {
+        let mut mc = MConfig::from_str_pairs(&[("keep", "1"), ("change", "2"), ("drop", "3")], None).unwrap();
+        mc.try_insert("flag", None).unwrap();
+
+        mc.apply_json_patch(r#"{"change": "two", "drop": null, "new": "4", "missing": null}"#)
+            .unwrap();
+        assert_eq!(
+            mc.to_sorted_vec(),
+            [
+                ("change".to_string(), Some("two".to_string())),
+                ("flag".to_string(), None),
+                ("keep".to_string(), Some("1".to_string())),
+                ("new".to_string(), Some("4".to_string())),
+            ]
+        );
+
+        mc.apply_json_patch("{}").unwrap();
+        assert_eq!(mc.len(), 4);
+
+        for bad in ["[]", r#"{"keep": 1}"#, "{"] {
+            assert_eq!(mc.apply_json_patch(bad), Err(MCError::InvalidFormat));
+        }
+        let too_long = format!(r#"{{"keep": null, "v": "{}"}}"#, "v".repeat(256));
+        assert_eq!(mc.apply_json_patch(&too_long), Err(MCError::ValueTooBig));
+        assert!(mc.contains_key("keep"));
+    }
+
+    #[test]
+    fn empty_key_is_rejected() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap();
+
+        assert_eq!(mc.insert_str("", Some("lost")), Err(MCError::EmptyKey));
+        assert_eq!(mc.try_extend([(String::new(), None)]), Err(MCError::EmptyKey));
+        assert_eq!(
+            MConfig::try_from(HashMap::from([(String::new(), None)])).err(),
+            Some(MCError::EmptyKey)
+        );
+
+        // an empty key would have been written as the terminator, hiding every entry after it
+        let loaded = MConfig::builder().load(mc.to_vec()).try_build().unwrap();
+        assert_eq!(loaded.to_sorted_vec(), mc.to_sorted_vec());
+    }
+
+    #[test]
+    fn shrink_to_smallest_class() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        for i in 0..20 {
+            mc.try_insert(format!("k{i:02}"), Some("v".repeat(255))).unwrap();
+        }
+        for i in 0..20 {
+            mc.remove(&format!("k{i:02}"));
+        }
+
+        assert_eq!(mc.shrink_to_smallest(), Ok(1_024));
+        assert_eq!(mc.size_class(), SizeClass::Tiny1K);
+        let bytes = mc.to_vec();
+        assert_eq!(bytes.len(), 1_024);
+        assert_eq!(MConfig::load_verified(&bytes, "TACOS").unwrap()["Hello"], Some("World".to_string()));
+
+        for i in 0..20 {
+            mc.try_insert(format!("k{i:02}"), Some("v".repeat(10))).unwrap();
+        }
+        assert_eq!(mc.shrink_to_smallest(), Ok(1_024));
+        mc.set_block_size(16_384).unwrap();
+        for i in 0..40 {
+            mc.try_insert(format!("k{i:02}"), Some("v".repeat(255))).unwrap();
+        }
+        assert_eq!(mc.shrink_to_smallest(), Ok(16_384));
+
+        // a custom block below the smallest class is never grown
+        let mut small = MConfig::builder().block_size(512).try_build().unwrap();
+        small.insert_str("Hello", Some("World")).unwrap();
+        assert_eq!(small.shrink_to_smallest(), Ok(512));
+        assert_eq!(small.block_size(), 512);
+
+        let mut v0 = MConfig::builder().version(0).try_build().unwrap();
+        assert_eq!(v0.shrink_to_smallest(), Ok(8_192));
+    }
+
+    #[test]
+    fn hex_dump_labels_sections() {
+        let mc = MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap();
+        let dump = mc.to_hex_dump();
+
+        assert!(dump.starts_with("-- header, version 1: 12 bytes at 0x0000 --\n00000000  4d 43 4f 4e 46 01 00 40"));
+        assert!(dump.contains("-- entries, 1: 12 bytes at 0x000c --\n0000000c  05 48 65 6c 6c 6f 05 57"));
+        assert!(dump.contains("|.Hello.World|"));
+        assert!(dump.contains("-- terminator: 1 bytes at 0x0018 --\n00000018  00"));
+        assert!(dump.contains("-- padding: 8167 bytes at 0x0019 --"));
+        assert!(!dump.contains("sentinel"));
+
+        let xor = MConfig::builder().secret("TACOS").algorithm(Algorithm::Xor).try_build().unwrap();
+        assert!(xor.to_hex_dump().contains("-- sentinel: 4 bytes at 0x000c --"));
+    }
+
+    #[test]
+    fn entries_bytes_without_padding() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], None).unwrap();
+        mc.try_insert("Empty", None).unwrap();
+        let entries = mc.entries_bytes();
+        assert_eq!(entries.len(), 5 + 2 + 5 + 4 + 2 + 7 + 5 + 2 + 1);
+        assert_eq!(entries.last(), Some(&0));
+
+        let bytes = mc.to_vec();
+        let header_size = MConfig::read_header(&bytes).unwrap().size();
+        assert_eq!(&bytes[header_size..header_size + entries.len()], entries);
+
+        let mut rebuilt = bytes[..header_size].to_vec();
+        rebuilt.extend_from_slice(&entries);
+        rebuilt.resize(bytes.len(), 0);
+        let reparsed = MConfig::builder().try_build_ref(&rebuilt).unwrap();
+        assert_eq!(reparsed.to_sorted_vec(), mc.to_sorted_vec());
+
+        assert_eq!(MConfig::builder().try_build().unwrap().entries_bytes(), [0]);
+    }
+
+    #[test]
+    fn entry_limits_count_utf8_bytes() {
+        let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).try_build().unwrap();
+
+        // 85 three-byte characters are 255 bytes, one more byte is over the limit
+        let key = "€".repeat(85);
+        assert_eq!(key.len(), 255);
+        assert_eq!(mc.insert_str(key.clone(), Some("v")), Ok(None));
+        assert_eq!(mc.insert_str(format!("{key}a"), Some("v")), Err(MCError::KeyTooBig));
+
+        let value = format!("{}a", "é".repeat(127));
+        assert_eq!(value.len(), 255);
+        assert_eq!(mc.try_insert("k", Some(value.clone())), Ok(None));
+        assert_eq!(mc.try_insert("k", Some(format!("{value}é"))), Err(MCError::ValueTooBig));
+        assert_eq!(mc.try_insert("k", Some("é".repeat(128))), Err(MCError::ValueTooBig));
+
+        let loaded = MConfig::load_verified(&mc.to_vec(), "TACOS").unwrap();
+        assert_eq!(loaded[key.as_str()], Some("v".to_string()));
+        assert_eq!(loaded["k"], Some(value));
+    }
+
+    #[test]
+    fn v2_round_trips_long_entries() {
+        let key = "k".repeat(300);
+        let value = "v".repeat(1_000);
+        for algorithm in [Algorithm::Xor, Algorithm::XorValues, Algorithm::ChaCha20Poly1305] {
+            let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).algorithm(algorithm).version(2).try_build().unwrap();
+            assert_eq!(mc.max_value_len(), 65_535);
+            mc.try_insert(key.clone(), Some(value.clone())).unwrap();
+            mc.try_insert("Bye", None).unwrap();
+
+            let bytes = mc.to_vec();
+            assert_eq!(MConfig::read_header(&bytes).unwrap().version(), 2);
+            let loaded = MConfig::load_verified(&bytes, "TACOS").unwrap();
+            assert_eq!(loaded[key.as_str()], Some(value.clone()));
+            assert_eq!(loaded["Bye"], None);
+            assert_eq!(MConfigView::new(&bytes, Some("TACOS")).unwrap().get(&key), Some(value.as_str()));
+        }
+
+        let mut mc = MConfig::builder().version(2).try_build().unwrap();
+        mc.try_insert("Hello", Some(value.clone())).unwrap();
+        let bytes = mc.to_vec();
+        let header_size = MConfig::read_header(&bytes).unwrap().size();
+        assert_eq!(bytes[header_size..header_size + 8], [5, 0, b'H', b'e', b'l', b'l', b'o', 0xe8]);
+        assert_eq!(bytes[header_size + 8], 0x03);
+        assert_eq!(mc.entries_bytes().len(), 2 + 5 + 2 + 1_000 + 2);
+        assert_eq!(mc.canonical_bytes(), mc.entries_bytes());
+
+        // still bounded by the block size, and v1 cannot hold the long value
+        assert!(matches!(mc.try_insert("big", Some("v".repeat(8_000))), Err(MCError::TooBig { .. })));
+        assert_eq!(mc.set_version(1), Err(MCError::ValueTooBig));
+        mc.insert_str("Hello", Some("World")).unwrap();
+        mc.set_version(1).unwrap();
+        assert_eq!(MConfig::builder().try_build_ref(&mc.to_vec()).unwrap()["Hello"], Some("World".to_string()));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn secret_zeroized_on_drop() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        mc.set_secret(Some("BURRITOS".to_string()));
+        mc.rehash(KdfParams::new(16));
+        let bytes = mc.to_vec();
+        drop(mc);
+
+        let builder = MConfig::builder().secret("BURRITOS");
+        let loaded = builder.try_build_ref(&bytes).unwrap();
+        assert_eq!(loaded.into_iter().collect::<Vec<_>>(), [("Hello".to_string(), Some("World".to_string()))]);
+        drop(MConfig::builder().secret("TACOS"));
+    }
+
+    #[test]
+    fn xor_round_trip_all_versions() {
+        for version in [0, 1, 2] {
+            let mut mc = MConfig::builder().secret("TACOS").algorithm(Algorithm::Xor).version(version).try_build().unwrap();
+            mc.insert_str("Hello", Some("World")).unwrap();
+            mc.try_insert("Bye", None).unwrap();
+
+            let bytes = mc.to_vec();
+            assert!(!bytes.windows(5).any(|w| w == b"Hello"));
+            let loaded = MConfig::load_verified(&bytes, "TACOS").unwrap();
+            assert_eq!(loaded.to_sorted_vec(), mc.to_sorted_vec());
+        }
+    }
+
+    #[test]
+    fn try_insert_many_is_all_or_nothing() {
+        let mut mc = MConfig::builder().secret("TACOS").block_size(1_024).try_build().unwrap();
+        mc.insert_str("Hello", Some("World")).unwrap();
+
+        let mut pairs: Vec<(String, Option<String>)> =
+            (0..3).map(|i| (format!("k{i}"), Some("v".repeat(255)))).collect();
+        pairs.push(("Hello".to_string(), None));
+        pairs.push(("last".to_string(), Some("v".repeat(255))));
+        assert!(matches!(mc.try_insert_many(pairs.clone()), Err(MCError::TooBig { .. })));
+        assert_eq!(mc.to_sorted_vec(), [("Hello".to_string(), Some("World".to_string()))]);
+
+        pairs.pop();
+        mc.try_insert_many(pairs).unwrap();
+        assert_eq!(mc.len(), 4);
+        assert_eq!(mc["Hello"], None);
+    }
+
+    #[test]
+    fn eq_compares_entries_only() {
+        let mut a = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], Some("TACOS")).unwrap();
+        a.rehash(KdfParams::new(16));
+        let mut b = MConfig::builder().algorithm(Algorithm::Xor).version(0).try_build().unwrap();
+        b.insert_str("Taco", Some("Tuesday")).unwrap();
+        b.insert_str("Hello", Some("World")).unwrap();
+        assert!(a == b);
+        assert!(MConfig::load_verified(&a.to_vec(), "TACOS").unwrap() == a);
+
+        b.try_insert("Hello", None).unwrap();
+        assert!(a != b);
+        b.insert_str("Hello", Some("World")).unwrap();
+        b.try_insert("Bye", None).unwrap();
+        assert!(a != b);
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let mut original = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        original.rehash(KdfParams::new(16));
+        let mut clone = original.clone();
+        clone.insert_str("Hello", Some("Moon")).unwrap();
+        clone.try_insert("Bye", None).unwrap();
+        clone.set_secret(Some("BURRITOS".to_string()));
+
+        assert_eq!(original.to_sorted_vec(), [("Hello".to_string(), Some("World".to_string()))]);
+        assert_eq!(MConfig::load_verified(&original.to_vec(), "TACOS").unwrap()["Hello"], Some("World".to_string()));
+        assert_eq!(MConfig::load_verified(&clone.to_vec(), "BURRITOS").unwrap()["Hello"], Some("Moon".to_string()));
+    }
+
+    #[test]
+    fn version_of_loaded_v0_block() {
+        let mut region = b"\x05Hello\x05World\x00".to_vec();
+        region.resize(8_192 - 6, 0);
+        let mut bytes = vec![0x4d, 0x43, 0x4f, 0x4e, 0x46, 0x00];
+        bytes.extend(MConfig::xor_with_secret(&region, b"TACOS"));
+
+        let mut mc = MConfig::load_verified(&bytes, "TACOS").unwrap();
+        assert_eq!(mc.version(), 0);
+        assert_eq!(mc["Hello"], Some("World".to_string()));
+
+        mc.set_version(2).unwrap();
+        assert_eq!(mc.version(), 2);
+        assert_eq!(MConfig::builder().try_build().unwrap().version(), 1);
+    }
+
+    #[test]
+    fn reader_short_input_fails() {
+        let cursor = Cursor::new(vec![0u8; 3]);
+        assert_eq!(
+            MConfig::from_reader(cursor, None).err(),
+            Some(MCError::Io(std::io::ErrorKind::UnexpectedEof))
+        );
+
+        let mut truncated = MConfig::empty_blob(None);
+        truncated.truncate(100);
+        assert_eq!(
+            MConfig::from_reader(Cursor::new(truncated), None).err(),
+            Some(MCError::Io(std::io::ErrorKind::UnexpectedEof))
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::mconfigurator::mconfig_header::Header;
+use crate::mconfigurator::{MCError, MConfig, MCResult};
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, Tag};
+use sha2::{Digest, Sha256};
+
+const NONCE_LEN: usize = 12;
+
+impl MConfig {
+    /// Encrypt the entries region in place with ChaCha20-Poly1305 under a fresh random nonce.
+    /// The nonce and tag are stored in the header, and the rest of the header is authenticated with the entries.
+    pub(crate) fn seal(mut buffer: Vec<u8>, key: &[u8], header: &mut Header) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let mut aead = [0u8; MConfig::AEAD_LEN];
+        aead[..NONCE_LEN].copy_from_slice(&nonce);
+        header.aead = Some(aead);
+
+        let tag = MConfig::cipher(key)
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce), &header.to_vec(), &mut buffer)
+            .expect("the entries region is far below the cipher's length limit");
+        aead[NONCE_LEN..].copy_from_slice(&tag);
+        header.aead = Some(aead);
+        buffer
+    }
+
+    /// Decrypt an entries region sealed by `seal`. Fails with `DecryptionFailed` if the tag does not match,
+    /// which means the secret is wrong or the header or entries were altered.
+    pub(crate) fn unseal(mut buffer: Vec<u8>, key: &[u8], header: &Header) -> MCResult<Vec<u8>> {
+        let aead = header.aead.ok_or(MCError::BadHeader)?;
+        let (nonce, tag) = aead.split_at(NONCE_LEN);
+
+        // the tag was computed while its own field was still zeroed
+        let mut unsealed = *header;
+        let mut zeroed = aead;
+        zeroed[NONCE_LEN..].fill(0);
+        unsealed.aead = Some(zeroed);
+
+        MConfig::cipher(key)
+            .decrypt_in_place_detached(Nonce::from_slice(nonce), &unsealed.to_vec(), &mut buffer, Tag::from_slice(tag))
+            .map_err(|_| MCError::DecryptionFailed)?;
+        Ok(buffer)
+    }
+
+    /// The cipher keyed with a SHA-256 hash of the obfuscation key, which is always derived with the KDF.
+    fn cipher(key: &[u8]) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&Sha256::digest(key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    fn sealed(secret: &str) -> Vec<u8> {
+        let mut mc = MConfig::builder().secret(secret).kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        mc.try_insert("Taco".to_string(), Some("Tuesday".to_string())).unwrap();
+        assert_eq!(MConfig::read_header(&mc.to_vec()).unwrap().algorithm(), Algorithm::ChaCha20Poly1305);
+        mc.to_vec()
+    }
+
+    #[test]
+    fn sealed_round_trip() {
+        let loaded = MConfig::builder().secret("TACOS").load(sealed("TACOS")).try_build().unwrap();
+        assert_eq!(loaded["Hello"], Some("World".to_string()));
+        assert_eq!(loaded["Taco"], Some("Tuesday".to_string()));
+    }
+
+    #[test]
+    fn wrong_secret_fails_to_decrypt() {
+        let bytes = sealed("TACOS");
+        for secret in ["tacos", "TACOS "] {
+            let result = MConfig::builder().secret(secret).try_build_ref(&bytes);
+            assert_eq!(result.err(), Some(MCError::DecryptionFailed));
+        }
+        assert_eq!(MConfigView::new(&bytes, Some("tacos")).err(), Some(MCError::DecryptionFailed));
+        assert_eq!(MConfig::builder().secret("").try_build_ref(&bytes).err(), Some(MCError::EmptySecret));
+    }
+
+    #[test]
+    fn tampering_fails_to_decrypt() {
+        let bytes = sealed("TACOS");
+        let header_size = MConfig::read_header(&bytes).unwrap().size();
+
+        // one flipped bit in the entries, the padding, or the authenticated header
+        for index in [header_size, bytes.len() - 1, MConfig::VERSION_INDEX + 1] {
+            let mut tampered = bytes.clone();
+            tampered[index] ^= 1;
+            let result = MConfig::builder().secret("TACOS").try_build_ref(&tampered);
+            assert!(result.is_err());
+        }
+
+        let mut tampered = bytes.clone();
+        tampered[header_size + 3] ^= 0x40;
+        let result = MConfig::builder().secret("TACOS").try_build_ref(&tampered);
+        assert_eq!(result.err(), Some(MCError::DecryptionFailed));
+    }
+
+    #[test]
+    fn key_derived_with_default_kdf() {
+        let mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        let first = MConfig::read_header(&mc.to_vec()).unwrap().kdf().unwrap();
+        let second = MConfig::read_header(&mc.to_vec()).unwrap().kdf().unwrap();
+
+        assert_eq!(first.iterations(), KdfParams::DEFAULT_ITERATIONS);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn missing_kdf_is_bad_header() {
+        let mut bytes = sealed("TACOS");
+        bytes[MConfig::FLAGS_INDEX] &= !MConfig::KDF_FLAG;
+        assert_eq!(MConfig::read_header(&bytes).err(), Some(MCError::BadHeader));
+    }
+}
@@ -1,9 +1,18 @@
-use crate::mconfigurator::{MCError, MCHashMap, MConfig, MCResult};
+use crate::mconfigurator::mconfig_header::Header;
+use crate::mconfigurator::mconfig_records::Records;
+use crate::mconfigurator::{Algorithm, DuplicatePolicy, KdfParams, MCError, MCHashMap, MConfig, MCResult};
 
 /// Builder for the MConfig struct
 pub struct MConfigBuilder {
     secret: Option<String>,
     raw_bytes: Option<Vec<u8>>,
+    version: Option<u8>,
+    algorithm: Option<Algorithm>,
+    on_duplicate: DuplicatePolicy,
+    fingerprint: Option<bool>,
+    kdf: Option<KdfParams>,
+    block_size: Option<usize>,
+    sorted: bool,
 }
 
 impl MConfigBuilder {
@@ -12,6 +21,13 @@ impl MConfigBuilder {
         MConfigBuilder {
             secret: None,
             raw_bytes: None,
+            version: None,
+            algorithm: None,
+            on_duplicate: DuplicatePolicy::LastWins,
+            fingerprint: None,
+            kdf: None,
+            block_size: None,
+            sorted: false,
         }
     }
 
@@ -21,107 +37,213 @@ impl MConfigBuilder {
         self
     }
 
+    /// Sets the format version the resulting MConfig will be written as.
+    /// This can be used to keep writing an older version for readers that only understand it.
+    pub fn version(mut self, version: u8) -> MConfigBuilder {
+        self.version = Some(version);
+        self
+    }
+
+    /// Sets the obfuscation algorithm used when a secret is set. The default is `Algorithm::ChaCha20Poly1305`.
+    /// Version 0 only supports `Algorithm::Xor`.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> MConfigBuilder {
+        self.algorithm = Some(algorithm);
+        self
+    }
+
+    /// Sets how a key that appears more than once in the loaded data is handled.
+    /// Well-formed data never repeats a key, so this only matters for corrupted or tampered data.
+    /// The default is `DuplicatePolicy::LastWins`.
+    pub fn on_duplicate(mut self, policy: DuplicatePolicy) -> MConfigBuilder {
+        self.on_duplicate = policy;
+        self
+    }
+
+    /// Sets whether a fingerprint of the secret is stored in the header, so that loading with the wrong
+    /// secret fails early with `WrongSecret`. It is only written for version 1 or later with a secret.
+    /// By default loaded data keeps its fingerprint, if any, and a new MConfig has none.
+    /// The fingerprint is derived from the obfuscation key under a per-file salt. Without `kdf`, which
+    /// `Algorithm::Xor` and `Algorithm::XorValues` do not use by default, this weakens the file: it lets
+    /// a guessed secret be confirmed offline with a single HMAC.
+    pub fn fingerprint(mut self, enabled: bool) -> MConfigBuilder {
+        self.fingerprint = Some(enabled);
+        self
+    }
+
+    /// Sets the length in bytes of the serialized block, see `MConfig::set_block_size`.
+    /// A loaded block keeps the size recorded in its header unless this is set. The default is 8,192.
+    pub fn block_size(mut self, size: usize) -> MConfigBuilder {
+        self.block_size = Some(size);
+        self
+    }
+
+    /// Sets whether entries are written sorted by key, so that the same contents always serialize to the
+    /// same entries region, see `MConfig::normalize`. The default is false, which writes them in arbitrary order.
+    pub fn sorted(mut self, sorted: bool) -> MConfigBuilder {
+        self.sorted = sorted;
+        self
+    }
+
+    /// Sets the parameters for deriving the obfuscation key from the secret, replacing any loaded ones.
+    /// Without them the secret is used directly, except that `Algorithm::ChaCha20Poly1305` uses
+    /// `KdfParams::default()`. They are only used with a secret and version 1 or later.
+    pub fn kdf(mut self, params: KdfParams) -> MConfigBuilder {
+        self.kdf = Some(params);
+        self
+    }
+
+    /// Sets the secret if one is given, otherwise leaves the builder without a secret
+    pub(crate) fn optional_secret(mut self, secret: Option<&str>) -> MConfigBuilder {
+        self.secret = secret.map(|s| s.to_string());
+        self
+    }
+
     /// Loads raw bytes which may or may not be obfuscated
     pub fn load(mut self, raw_bytes: Vec<u8>) -> MConfigBuilder {
         self.raw_bytes = Some(raw_bytes);
         self
     }
     /// Attempt to parse a Vec<u8> into a viable hashmap.
-    fn try_parse(buffer: Vec<u8>, secret: &Option<String>, version: u8) -> MCResult<MCHashMap> {
-        let buffer = MConfig::deobfuscate(buffer, secret, version);
+    fn try_parse(
+        buffer: Vec<u8>,
+        key: Option<&[u8]>,
+        header: &Header,
+        on_duplicate: DuplicatePolicy,
+    ) -> MCResult<MCHashMap> {
+        let buffer = MConfig::deobfuscate(buffer, key, header)?;
 
         let mut entries = MCHashMap::new();
-        let mut value_iter = buffer.iter().copied();
-
-        while let Some(b) = value_iter.next() {
-            //key length
-            let key_len = b as usize;
-            //key length zero means end of data/start of padding
-            if key_len == 0 {
-                break;
-            }
-
-            let mut key_bytes: Vec<u8> = Vec::with_capacity(key_len);
-            for _ in 0..key_len {
-                match value_iter.next() {
-                    Some(k) => key_bytes.push(k),
-                    None => return Err(MCError::TruncatedKey),
-                }
-            }
+        for record in Records::new(&buffer, header.len_prefix_size()) {
+            let (key_bytes, val_bytes) = record?;
 
-            let key: String = match String::from_utf8(key_bytes) {
+            let key = match String::from_utf8(key_bytes.to_vec()) {
                 Ok(k) => k,
                 Err(_) => return Err(MCError::InvalidUTF8),
             };
 
-            let val_len = match value_iter.next() {
-                Some(v) => v as usize,
-                None => return Err(MCError::MissingKey),
+            let val = match val_bytes {
+                Some(v) => match String::from_utf8(v.to_vec()) {
+                    Ok(v) => Some(v),
+                    Err(_) => return Err(MCError::InvalidUTF8),
+                },
+                None => None,
             };
 
-            if val_len > 0 {
-                let mut val_bytes: Vec<u8> = Vec::with_capacity(val_len);
-                for _ in 0..val_len {
-                    match value_iter.next() {
-                        Some(v) => val_bytes.push(v),
-                        None => return Err(MCError::TruncatedValue),
-                    }
+            if entries.contains_key(&key) {
+                match on_duplicate {
+                    DuplicatePolicy::LastWins => {}
+                    DuplicatePolicy::FirstWins => continue,
+                    DuplicatePolicy::Error => return Err(MCError::DuplicateKey),
                 }
-
-                let val = match String::from_utf8(val_bytes) {
-                    Ok(v) => v,
-                    Err(_) => return Err(MCError::InvalidUTF8),
-                };
-
-                entries.insert(key, Some(val));
-            } else {
-                entries.insert(key, None); //valueless keys are allowed
             }
+            entries.insert(key, val);
         }
 
         Ok(entries)
     }
+
     /// Attempts to construct the MConfig object.
-    /// The resulting object will be of the most recent version.
-    /// This can fail if invalid raw data is loaded.
+    /// A new object will be of the default version 1 and a loaded one keeps the version of the raw data,
+    /// unless a version is set on the builder. Version 2, for keys and values longer than 255 bytes, must be asked for.
+    /// This can fail if invalid raw data is loaded or the contents cannot be represented in the requested version.
     /// Note that, while failure is likely if an invalid key is provided, it is not guaranteed.
-    pub fn try_build(self) -> MCResult<MConfig> {
-        let maybe_entries = match self.raw_bytes {
-            Some(raw) => {
-                if raw.len() < MConfig::HEADER_SIZE {
-                    return Err(MCError::TooShort); //minimum length
-                }
-                if raw.len() > MConfig::MCONFIG_SIZE {
-                    return Err(MCError::TooBig); //maximum length
-                }
+    pub fn try_build(mut self) -> MCResult<MConfig> {
+        let raw_bytes = self.raw_bytes.take();
+        self.build_from(raw_bytes.as_deref())
+    }
 
-                //check header magic
-                if raw[0..MConfig::MAGIC_HEADER_BYTES.len()] != MConfig::MAGIC_HEADER_BYTES {
-                    return Err(MCError::BadHeader);
-                }
+    /// Attempts to construct the MConfig object from borrowed raw bytes, which are used instead of any
+    /// loaded with `load`. The buffer is only read; the entries are copied into the MConfig as with `try_build`.
+    pub fn try_build_ref(mut self, raw_bytes: &[u8]) -> MCResult<MConfig> {
+        self.raw_bytes = None;
+        self.build_from(Some(raw_bytes))
+    }
+
+    fn build_from(self, raw_bytes: Option<&[u8]>) -> MCResult<MConfig> {
+        // an empty secret would leave the entries in the clear while looking obfuscated
+        if self.secret.as_deref() == Some("") {
+            return Err(MCError::EmptySecret);
+        }
 
-                //check and select version
-                if raw[MConfig::VERSION_INDEX] != 0u8 {
-                    return Err(MCError::UnknownVersion);
+        let maybe_entries = match raw_bytes {
+            Some(raw) => Header::parse(raw).and_then(|header| {
+                let key = header.key(self.secret.as_deref());
+                header.check_key(key.as_deref())?;
+                let entries = MConfigBuilder::try_parse(
+                    raw[header.size()..].to_owned(),
+                    key.as_deref(),
+                    &header,
+                    self.on_duplicate,
+                )?;
+                #[cfg(feature = "log")]
+                log::debug!("Loaded MConfig v{} data with {} entries", header.version, entries.len());
+                Ok((entries, Some(header)))
+            }),
+            None => Ok((MCHashMap::new(), None)),
+        };
+
+        let mut mc = match maybe_entries {
+            Ok((entries, header)) => {
+                // plain data records no algorithm to carry forward if a secret is set later
+                let algorithm = match header {
+                    Some(h) if h.algorithm != Algorithm::Plain => h.algorithm,
+                    _ => Algorithm::ChaCha20Poly1305,
+                };
+                MConfig {
+                    secret: self.secret.clone(),
+                    entries,
+                    version: header.map_or(MConfig::DEFAULT_VERSION, |h| h.version),
+                    algorithm: self.algorithm.unwrap_or(algorithm),
+                    sorted: self.sorted,
+                    fingerprint: self
+                        .fingerprint
+                        .unwrap_or(header.is_some_and(|h| h.fingerprint.is_some())),
+                    kdf: self.kdf.or(header.and_then(|h| h.kdf)),
+                    block_size: header.map_or(MConfig::MCONFIG_SIZE, |h| h.block_size()),
+                    rotate_after: header.and_then(|h| h.rotate_after),
                 }
-                let version = raw[MConfig::VERSION_INDEX];
-                MConfigBuilder::try_parse(raw[MConfig::HEADER_SIZE..].to_owned(), &self.secret, version)
             }
-            None => Ok(MCHashMap::new()),
+            Err(e) => {
+                // never log the secret or any entry contents
+                #[cfg(feature = "log")]
+                if self.secret.is_some() {
+                    log::warn!("Failed to load MConfig data: {}. The secret may be wrong.", e);
+                } else {
+                    log::warn!("Failed to load MConfig data: {}", e);
+                }
+                return Err(e);
+            }
         };
 
-        match maybe_entries {
-            Ok(entries) => Ok(MConfig {
-                secret: self.secret.clone(),
-                entries,
-                version: 0,
-            }),
-            Err(e) => Err(e),
+        if let Some(version) = self.version {
+            mc.set_version(version)?;
+        }
+        if let Some(size) = self.block_size {
+            mc.set_block_size(size)?;
+        }
+        Ok(mc)
+    }
+
+    /// Like `try_build`, but first checks the loaded data against a tag from `MConfig::to_vec_detached`.
+    /// Fails with `IntegrityFailure` if the tag does not match, e.g. because the data was altered
+    /// or the secret is wrong.
+    pub fn try_build_detached(self, mac: &[u8; 32]) -> MCResult<MConfig> {
+        if let Some(ref raw) = self.raw_bytes {
+            MConfig::verify_detached(raw, &self.secret, mac)?;
         }
+        self.try_build()
     }
 }
 
 
+/// With the `zeroize` feature the secret is overwritten before its memory is freed, as for `MConfig`.
+#[cfg(feature = "zeroize")]
+impl Drop for MConfigBuilder {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.secret);
+    }
+}
+
 impl Default for MConfigBuilder {
     fn default() -> Self {
         MConfigBuilder::new()
@@ -163,6 +285,7 @@ mod tests {
     fn to_and_from_vec() {
         let mut before_vec = MConfig::builder()
             .secret("I like TACOS")
+            .kdf(KdfParams::new(16))
             .try_build()
             .unwrap();
         before_vec
@@ -187,11 +310,371 @@ mod tests {
         );
     }
 
+    #[test]
+    fn max_length_key_and_value_round_trip() {
+        let key = "k".repeat(MConfig::MAX_KEY_LEN);
+        let value = "v".repeat(MConfig::MAX_VALUE_LEN);
+
+        let mut before_vec = MConfig::builder().secret("I like TACOS").kdf(KdfParams::new(16)).try_build().unwrap();
+        before_vec.try_insert(key.clone(), Some(value.clone())).unwrap();
+        before_vec.try_insert("after".to_string(), Some("boundary".to_string())).unwrap();
+
+        let mcv = before_vec.to_vec();
+
+        let after_vec = MConfig::builder()
+            .load(mcv)
+            .secret("I like TACOS")
+            .try_build()
+            .unwrap();
+
+        assert_eq!(after_vec.get(&key), Some(&Some(value)));
+        assert_eq!(after_vec.get("after"), Some(&Some("boundary".to_string())));
+
+        let mut too_long = MConfig::builder().try_build().unwrap();
+        assert_eq!(too_long.try_insert("k".repeat(MConfig::MAX_KEY_LEN + 1), None), Err(MCError::KeyTooBig));
+        assert_eq!(
+            too_long.try_insert("k".to_string(), Some("v".repeat(MConfig::MAX_VALUE_LEN + 1))),
+            Err(MCError::ValueTooBig)
+        );
+    }
+
+    #[test]
+    fn builder_version_writes_v0() {
+        let mut mc = MConfig::builder().secret("TACOS").version(0).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+
+        let mcv = mc.to_vec();
+        assert_eq!(mcv[MConfig::VERSION_INDEX], 0);
+
+        let loaded = MConfig::builder().load(mcv).secret("TACOS").try_build().unwrap();
+        assert_eq!(loaded.version, 0);
+        assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+    }
+
+    #[test]
+    fn builder_unknown_version_fails() {
+        assert_eq!(
+            MConfig::builder().version(MConfig::LATEST_VERSION + 1).try_build().err(),
+            Some(MCError::UnknownVersion)
+        );
+
+        let mut mc = MConfig::builder().try_build().unwrap();
+        assert_eq!(mc.set_version(MConfig::LATEST_VERSION + 1), Err(MCError::UnknownVersion));
+        assert_eq!(mc.version, MConfig::DEFAULT_VERSION);
+    }
+
+    #[cfg(feature = "log")]
+    mod logging {
+        use crate::mconfigurator::*;
+        use log::{Level, Log, Metadata, Record};
+        use std::sync::{Mutex, Once};
+
+        struct CaptureLogger {
+            records: Mutex<Vec<(Level, String)>>,
+        }
+
+        impl Log for CaptureLogger {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                self.records.lock().unwrap().push((record.level(), record.args().to_string()));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: CaptureLogger = CaptureLogger {
+            records: Mutex::new(Vec::new()),
+        };
+        static INIT: Once = Once::new();
+
+        #[test]
+        fn wrong_secret_logs_warning() {
+            INIT.call_once(|| {
+                log::set_logger(&LOGGER).unwrap();
+                log::set_max_level(log::LevelFilter::Trace);
+            });
+
+            let mut mc = MConfig::builder().secret("a").kdf(KdfParams::new(16)).try_build().unwrap();
+            mc.try_insert("é".to_string(), Some("hunter2".to_string())).unwrap();
+
+            let result = MConfig::builder().load(mc.to_vec()).secret("b").try_build();
+            assert_eq!(result.err(), Some(MCError::DecryptionFailed));
+
+            let records = LOGGER.records.lock().unwrap();
+            assert!(records
+                .iter()
+                .any(|(level, msg)| *level == Level::Warn && msg.contains("secret may be wrong")));
+            assert!(records.iter().all(|(_, msg)| !msg.contains("hunter2") && !msg.contains("\"b\"")));
+        }
+    }
+
+    #[test]
+    fn algorithm_tags_round_trip() {
+        for algorithm in [Algorithm::Plain, Algorithm::Xor] {
+            let mut mc = MConfig::builder()
+                .secret("I like TACOS")
+                .algorithm(algorithm)
+                .try_build()
+                .unwrap();
+            mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+
+            let mcv = mc.to_vec();
+            assert_eq!(mcv[MConfig::VERSION_INDEX], 1);
+            assert_eq!(mcv[MConfig::ALGORITHM_INDEX], algorithm.tag());
+            assert_eq!(
+                mcv.windows(5).any(|w| w == b"World"),
+                algorithm == Algorithm::Plain
+            );
+
+            let loaded = MConfig::builder().load(mcv).secret("I like TACOS").try_build().unwrap();
+            assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+        }
+    }
+
+    #[test]
+    fn unknown_algorithm_fails() {
+        let mut mcv = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).try_build().unwrap().to_vec();
+        mcv[MConfig::ALGORITHM_INDEX] = 0xee;
+
+        assert_eq!(
+            MConfig::builder().load(mcv).secret("TACOS").try_build().err(),
+            Some(MCError::UnknownAlgorithm)
+        );
+    }
+
+    /// A v0 block without a secret holding key "a" twice, first "1" then "2".
+    fn duplicate_key_block() -> Vec<u8> {
+        let mut raw = MConfig::MAGIC_HEADER_BYTES.to_vec();
+        raw.push(0);
+        raw.extend_from_slice(&[1, b'a', 1, b'1', 1, b'a', 1, b'2', 0]);
+        raw
+    }
+
+    #[test]
+    fn duplicate_policy_last_wins() {
+        let mc = MConfig::builder().load(duplicate_key_block()).try_build().unwrap();
+        assert_eq!(mc.get("a"), Some(&Some("2".to_string())));
+    }
+
+    #[test]
+    fn duplicate_policy_first_wins() {
+        let mc = MConfig::builder()
+            .load(duplicate_key_block())
+            .on_duplicate(DuplicatePolicy::FirstWins)
+            .try_build()
+            .unwrap();
+        assert_eq!(mc.get("a"), Some(&Some("1".to_string())));
+    }
+
+    #[test]
+    fn duplicate_policy_error() {
+        let result = MConfig::builder()
+            .load(duplicate_key_block())
+            .on_duplicate(DuplicatePolicy::Error)
+            .try_build();
+        assert_eq!(result.err(), Some(MCError::DuplicateKey));
+    }
+
+    #[test]
+    fn fingerprint_round_trip() {
+        let mut mc = MConfig::builder()
+            .secret("right")
+            .fingerprint(true)
+            .kdf(KdfParams::new(16))
+            .try_build()
+            .unwrap();
+        mc.try_insert("Key".to_string(), Some("Value".to_string())).unwrap();
+
+        let loaded = MConfig::builder().secret("right").load(mc.to_vec()).try_build().unwrap();
+
+        assert_eq!(loaded.get("Key"), Some(&Some("Value".to_string())));
+        assert_eq!(
+            loaded.to_vec()[MConfig::FLAGS_INDEX],
+            MConfig::FINGERPRINT_FLAG | MConfig::KDF_FLAG | MConfig::AEAD_FLAG | MConfig::CHECKSUM_FLAG
+        );
+    }
+
+    #[test]
+    fn fingerprint_wrong_secret_fails_fast() {
+        let mc = MConfig::builder().secret("right").fingerprint(true).kdf(KdfParams::new(16)).try_build().unwrap();
+        let mut bytes = mc.to_vec();
+        // wreck the entries so that only the header check can produce WrongSecret
+        let entries_start = MConfig::read_header(&bytes).unwrap().size();
+        bytes[entries_start..].fill(0xff);
+
+        let result = MConfig::builder().secret("wrong").load(bytes.clone()).try_build();
+        assert_eq!(result.err(), Some(MCError::WrongSecret));
+
+        let result = MConfig::builder().load(bytes).try_build();
+        assert_eq!(result.err(), Some(MCError::WrongSecret));
+    }
+
+    #[test]
+    fn fingerprint_salted_and_keyed_from_kdf() {
+        let mc = MConfig::builder().secret("right").fingerprint(true).algorithm(Algorithm::Xor).try_build().unwrap();
+        let first = MConfig::read_header(&mc.to_vec()).unwrap();
+        let second = MConfig::read_header(&mc.to_vec()).unwrap();
+        assert_ne!(first.fingerprint(), second.fingerprint());
+
+        let mc = MConfig::builder().secret("right").fingerprint(true).kdf(KdfParams::new(100)).try_build().unwrap();
+        let header = MConfig::read_header(&mc.to_vec()).unwrap();
+        assert!(header.check_key(header.key(Some("right")).as_deref()).is_ok());
+        // the bare secret does not match, only the derived key does
+        assert_eq!(header.check_key(Some(b"right")), Err(MCError::WrongSecret));
+    }
+
+    #[test]
+    fn fingerprint_not_written_for_v0() {
+        let mc = MConfig::builder().secret("right").fingerprint(true).version(0).try_build().unwrap();
+
+        let loaded = MConfig::builder().secret("right").load(mc.to_vec()).try_build();
+
+        assert!(loaded.is_ok());
+    }
+
+    #[test]
+    fn build_from_borrowed_slice() {
+        let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        let buffer = mc.to_vec();
+
+        let loaded = MConfig::builder().secret("TACOS").try_build_ref(&buffer).unwrap();
+        assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+
+        // the buffer is still ours
+        let again = MConfig::builder().secret("TACOS").load(buffer).try_build().unwrap();
+        assert_eq!(again.to_sorted_vec(), loaded.to_sorted_vec());
+    }
+
+    #[test]
+    fn block_size_round_trips() {
+        for size in [1_024, 8_192, 65_536] {
+            let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).block_size(size).try_build().unwrap();
+            mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+
+            let mut bytes = mc.to_vec();
+            assert_eq!(bytes.len(), size);
+
+            let loaded = MConfig::builder().secret("TACOS").load(bytes.clone()).try_build().unwrap();
+            assert_eq!(loaded.block_size(), size);
+            assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+
+            // the reader stops at the end of the block
+            bytes.extend_from_slice(b"trailing data");
+            let read = MConfig::from_reader(std::io::Cursor::new(bytes), Some("TACOS")).unwrap();
+            assert_eq!(read.block_size(), size);
+            assert_eq!(read.to_sorted_vec(), loaded.to_sorted_vec());
+        }
+    }
+
+    #[test]
+    fn block_size_limits_contents() {
+        let mut mc = MConfig::builder().block_size(1_024).try_build().unwrap();
+        assert_eq!(mc.size_class(), SizeClass::Tiny1K);
+        let mut i = 0;
+        while mc.try_insert(format!("k{i}"), Some("v".repeat(255))).is_ok() {
+            i += 1;
+        }
+        assert_eq!(i, 3);
+        assert!(matches!(mc.set_block_size(512), Err(MCError::TooBig { .. })));
+        assert_eq!(mc.block_size(), 1_024);
+
+        let custom = MConfig::builder().block_size(5_000).try_build().unwrap();
+        assert_eq!(custom.size_class(), SizeClass::Custom(5_000));
+
+        assert_eq!(MConfig::builder().block_size(16).try_build().err(), Some(MCError::InvalidFormat));
+        assert_eq!(
+            MConfig::builder().version(0).block_size(1_024).try_build().err(),
+            Some(MCError::InvalidFormat)
+        );
+
+        let mut oversized = MConfig::builder().block_size(1_024).try_build().unwrap().to_vec();
+        oversized.push(0);
+        assert_eq!(MConfig::builder().load(oversized).try_build().err(), Some(MCError::TooBig { over_by: 1 }));
+    }
+
+    #[test]
+    fn rotate_after_round_trips() {
+        let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        assert_eq!(mc.rotate_after(), None);
+
+        mc.set_rotate_after(1_800_000_000);
+        let loaded = MConfig::builder().secret("TACOS").load(mc.to_vec()).try_build().unwrap();
+
+        assert_eq!(loaded.rotate_after(), Some(1_800_000_000));
+        assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+    }
+
+    #[test]
+    fn sorted_layout_is_deterministic() {
+        let build = |keys: &[&str]| {
+            let mut mc = MConfig::builder().sorted(true).try_build().unwrap();
+            for k in keys {
+                mc.try_insert(k.to_string(), Some(k.to_uppercase())).unwrap();
+            }
+            mc
+        };
+        let a = build(&["delta", "alpha", "charlie", "bravo"]);
+        let b = build(&["bravo", "charlie", "alpha", "delta"]);
+
+        let used = a.used_len(None);
+        assert_eq!(used, b.used_len(None));
+        assert_eq!(a.to_vec()[..used], b.to_vec()[..used]);
+    }
+
+    #[test]
+    fn xor_sentinel_detects_wrong_secret() {
+        let mut mc = MConfig::builder().secret("I like TACOS").algorithm(Algorithm::Xor).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        let bytes = mc.to_vec();
+        assert_ne!(bytes[MConfig::FLAGS_INDEX] & MConfig::SENTINEL_FLAG, 0);
+
+        let loaded = MConfig::builder().secret("I like TACOS").try_build_ref(&bytes).unwrap();
+        assert_eq!(loaded["Hello"], Some("World".to_string()));
+
+        for secret in ["I hate TACOS", "i like TACOS", "I like TACOS!"] {
+            let result = MConfig::builder().secret(secret).try_build_ref(&bytes);
+            assert_eq!(result.err(), Some(MCError::WrongSecret));
+        }
+    }
+
+    #[test]
+    fn xor_without_sentinel_is_bad_header() {
+        let mut mc = MConfig::builder().secret("TACOS").algorithm(Algorithm::Xor).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        let mut bytes = mc.to_vec();
+
+        bytes[MConfig::FLAGS_INDEX] &= !MConfig::SENTINEL_FLAG;
+        let result = MConfig::builder().secret("TACOS").load(bytes).try_build();
+        assert_eq!(result.err(), Some(MCError::BadHeader));
+    }
+
+    #[test]
+    fn empty_secret_fails() {
+        assert_eq!(MConfig::builder().secret("").try_build().err(), Some(MCError::EmptySecret));
+
+        let bytes = MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap().to_vec();
+        assert_eq!(MConfig::builder().secret("").try_build_ref(&bytes).err(), Some(MCError::EmptySecret));
+        assert_eq!(MConfig::load_verified(&bytes, "").err(), Some(MCError::EmptySecret));
+        assert_eq!(MConfigView::new(&bytes, Some("")).err(), Some(MCError::EmptySecret));
+
+        let mut mc = MConfig::builder().secret("TACOS").try_build().unwrap();
+        mc.set_secret(Some(String::new()));
+        assert_eq!(mc.try_to_vec(), Err(MCError::EmptySecret));
+        mc.set_secret(None);
+        assert!(mc.try_to_vec().is_ok());
+    }
+
     #[test]
     #[should_panic]
     fn bad_key_fails() {
         let mut before_vec = MConfig::builder()
             .secret("I like TACOS")
+            .kdf(KdfParams::new(16))
             .try_build()
             .unwrap();
         before_vec
@@ -219,6 +702,7 @@ mod tests {
     #[test]
     fn maximum_length_fails() {
         let mut testmcnf = MConfig::builder()
+            .version(0)
             .try_build()
             .unwrap();
 
@@ -230,6 +714,9 @@ mod tests {
             testmcnf.try_insert(k, Some("1234".to_string())).expect("Too big too soon");
         }
 
-        assert_eq!(testmcnf.try_insert("final_key".to_string(), Some("oops".to_string())), Err(MCError::TooBig));
+        assert_eq!(
+            testmcnf.try_insert("final_key".to_string(), Some("oops".to_string())),
+            Err(MCError::TooBig { over_by: 14 })
+        );
     }
 }
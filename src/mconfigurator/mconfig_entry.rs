@@ -0,0 +1,91 @@
+use crate::mconfigurator::{MConfig, MCResult};
+
+/// A key of an MConfig that may or may not be set, like `hash_map::Entry`, from `MConfig::entry`.
+/// Every change goes through `try_insert`, so the length and block size limits still apply.
+pub struct MConfigEntry<'a> {
+    mc: &'a mut MConfig,
+    key: String,
+}
+
+impl<'a> MConfigEntry<'a> {
+    /// The key of this entry.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Retrieve the value, inserting `value` first if the key is not set.
+    /// The insert can fail like `try_insert`, leaving the key unset.
+    pub fn or_insert(self, value: Option<String>) -> MCResult<&'a Option<String>> {
+        self.or_insert_with(|| value)
+    }
+
+    /// Retrieve the value, inserting the value computed by `f` first if the key is not set.
+    /// `f` is only called if the key is absent. The insert can fail like `try_insert`, leaving the key unset.
+    pub fn or_insert_with<F: FnOnce() -> Option<String>>(self, f: F) -> MCResult<&'a Option<String>> {
+        let mc = self.mc;
+        if !mc.entries.contains_key(&self.key) {
+            mc.try_insert(self.key.clone(), f())?;
+        }
+        mc.try_get(&self.key)
+    }
+
+    /// Change the value with `f` if the key is set, and return the entry for chaining.
+    /// `f` works on a copy that is stored with `try_insert`, so if that fails the value is left unchanged.
+    pub fn and_modify<F: FnOnce(&mut Option<String>)>(self, f: F) -> MCResult<MConfigEntry<'a>> {
+        if let Some(value) = self.mc.entries.get(&self.key) {
+            let mut value = value.clone();
+            f(&mut value);
+            self.mc.try_insert(self.key.clone(), value)?;
+        }
+        Ok(self)
+    }
+}
+
+impl MConfig {
+    /// Get the entry for `key` to read or change it in place, like `HashMap::entry`.
+    pub fn entry(&mut self, key: String) -> MConfigEntry<'_> {
+        MConfigEntry { mc: self, key }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn entry_absent_key() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+
+        let entry = mc.entry("Taco".to_string());
+        assert_eq!(entry.key(), "Taco");
+        assert_eq!(entry.or_insert(Some("Tuesday".to_string())), Ok(&Some("Tuesday".to_string())));
+        assert_eq!(mc.entry("Bye".to_string()).or_insert(None), Ok(&None));
+
+        let modified = mc.entry("Absent".to_string()).and_modify(|_| panic!("not called")).unwrap();
+        assert_eq!(modified.or_insert_with(|| Some("new".to_string())), Ok(&Some("new".to_string())));
+        assert_eq!(mc.len(), 4);
+
+        assert_eq!(mc.entry("k".repeat(256)).or_insert(None), Err(MCError::KeyTooBig));
+        assert!(!mc.contains_key(&"k".repeat(256)));
+    }
+
+    #[test]
+    fn entry_present_key() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+
+        let value = mc.entry("Hello".to_string()).or_insert_with(|| panic!("not called"));
+        assert_eq!(value, Ok(&Some("World".to_string())));
+
+        let value = mc
+            .entry("Hello".to_string())
+            .and_modify(|v| v.as_mut().unwrap().push('!'))
+            .unwrap()
+            .or_insert(None);
+        assert_eq!(value, Ok(&Some("World!".to_string())));
+
+        // a change that does not fit leaves the value alone
+        let result = mc.entry("Hello".to_string()).and_modify(|v| *v = Some("v".repeat(256)));
+        assert_eq!(result.err(), Some(MCError::ValueTooBig));
+        assert_eq!(mc["Hello"], Some("World!".to_string()));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::mconfigurator::{MCError, MConfig, MCResult};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// An open MConfig file, held for a whole read-modify-write cycle.
+/// With the `lock` feature an exclusive advisory lock is taken when the file is opened, so that
+/// another process doing the same fails instead of clobbering the file. Files opened only for reading
+/// take a shared lock instead, which other readers can hold at the same time. The lock is released on drop.
+pub struct MConfigFile {
+    file: File,
+    read_only: bool,
+}
+
+impl MConfigFile {
+    /// Open an existing file for reading and writing.
+    /// Fails with `MCError::Io(io::ErrorKind::WouldBlock)` if another process holds the lock.
+    pub fn open<P: AsRef<Path>>(path: P) -> MCResult<MConfigFile> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        MConfigFile::lock(&file, false)?;
+        Ok(MConfigFile { file, read_only: false })
+    }
+
+    /// Open an existing file for reading only, e.g. when it is read-only on disk.
+    /// `store` fails on the returned handle with `MCError::Io(io::ErrorKind::PermissionDenied)`.
+    /// Fails with `MCError::Io(io::ErrorKind::WouldBlock)` if another process holds the exclusive lock.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> MCResult<MConfigFile> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        MConfigFile::lock(&file, true)?;
+        Ok(MConfigFile { file, read_only: true })
+    }
+
+    /// Open a file for reading and writing, creating it if it does not exist.
+    /// Existing contents are left alone until `store` is called.
+    /// Fails with `MCError::Io(io::ErrorKind::WouldBlock)` if another process holds the lock.
+    pub fn create<P: AsRef<Path>>(path: P) -> MCResult<MConfigFile> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        MConfigFile::lock(&file, false)?;
+        Ok(MConfigFile { file, read_only: false })
+    }
+
+    #[cfg(feature = "lock")]
+    fn lock(file: &File, shared: bool) -> io::Result<()> {
+        let locked = match shared {
+            true => fs2::FileExt::try_lock_shared(file),
+            false => fs2::FileExt::try_lock_exclusive(file),
+        };
+        match locked {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "file is locked by another process",
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cfg(not(feature = "lock"))]
+    fn lock(_file: &File, _shared: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Read and parse the file contents.
+    pub fn load(&mut self, secret: Option<&str>) -> MCResult<MConfig> {
+        self.file.seek(SeekFrom::Start(0))?;
+        MConfig::from_reader(&self.file, secret)
+    }
+
+    /// Replace the file contents with the serialized MConfig.
+    /// The file is left untouched if the MConfig cannot be serialized, or if it was opened read-only.
+    pub fn store(&mut self, mc: &MConfig) -> MCResult<()> {
+        if self.read_only {
+            return Err(MCError::Io(io::ErrorKind::PermissionDenied));
+        }
+        let v = mc.try_to_vec()?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.set_len(0)?;
+        self.file.write_all(&v)?;
+        Ok(self.file.sync_all()?)
+    }
+}
+
+impl MConfig {
+    /// Read and parse the file at path, holding a shared lock only while reading. The file only needs to
+    /// be readable. Use `MConfigFile` directly to keep the lock across a read-modify-write cycle.
+    pub fn open<P: AsRef<Path>>(path: P, secret: Option<&str>) -> MCResult<MConfig> {
+        MConfigFile::open_read_only(path)?.load(secret)
+    }
+
+    /// Try to open every file in a directory, returning the result for each one sorted by path.
+    /// A file that fails to open or parse does not stop the scan. Subdirectories are skipped.
+    pub fn open_dir<P: AsRef<Path>>(dir: P, secret: Option<&str>) -> MCResult<Vec<(PathBuf, MCResult<MConfig>)>> {
+        let mut results = Vec::new();
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                let mc = MConfig::open(&path, secret);
+                results.push((path, mc));
+            }
+        }
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(results)
+    }
+
+    /// Write the serialized MConfig to the file at path, holding the lock while writing.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> MCResult<()> {
+        MConfigFile::create(path)?.store(self)
+    }
+
+    /// Write the serialized MConfig to the file at path without taking a lock, like `Save` in the C# interface.
+    /// Contents that cannot be serialized are reported as `io::ErrorKind::InvalidInput`, as with `to_writer`.
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let v = self
+            .try_to_vec()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        std::fs::write(path, v)
+    }
+
+    /// Read the whole file at path and parse it with the builder, without taking a lock.
+    pub fn load_from_file(path: impl AsRef<Path>, secret: Option<&str>) -> MCResult<MConfig> {
+        let raw = std::fs::read(path)?;
+        MConfig::builder().optional_secret(secret).load(raw).try_build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn save_and_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mcf");
+
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        mc.save(&path).unwrap();
+
+        let loaded = MConfig::open(&path, Some("TACOS")).unwrap();
+        assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+    }
+
+    #[test]
+    fn open_dir_reports_each_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        mc.save(dir.path().join("a_valid.mcf")).unwrap();
+        std::fs::write(dir.path().join("b_invalid.mcf"), b"not an mconfig file").unwrap();
+        std::fs::create_dir(dir.path().join("c_subdir")).unwrap();
+
+        let results = MConfig::open_dir(dir.path(), Some("TACOS")).unwrap();
+        assert_eq!(results.len(), 2);
+
+        assert!(results[0].0.ends_with("a_valid.mcf"));
+        let valid = results[0].1.as_ref().unwrap();
+        assert_eq!(valid.get("Hello"), Some(&Some("World".to_string())));
+
+        assert!(results[1].0.ends_with("b_invalid.mcf"));
+        assert!(results[1].1.is_err());
+    }
+
+    #[test]
+    fn permission_error_is_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("readonly.mcf");
+        MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap().save(&path).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let mut file = MConfigFile::open_read_only(&path).unwrap();
+        assert_eq!(
+            file.store(&MConfig::builder().try_build().unwrap()).err(),
+            Some(MCError::Io(std::io::ErrorKind::PermissionDenied))
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_file_opens() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("readonly.mcf");
+        MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap().save(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o400)).unwrap();
+
+        assert_eq!(MConfig::open(&path, None).unwrap()["Hello"], Some("World".to_string()));
+        let results = MConfig::open_dir(dir.path(), None).unwrap();
+        assert!(results[0].1.is_ok());
+
+        let mut file = MConfigFile::open_read_only(&path).unwrap();
+        let mc = file.load(None).unwrap();
+        assert_eq!(file.store(&mc).err(), Some(MCError::Io(std::io::ErrorKind::PermissionDenied)));
+    }
+
+    #[cfg(feature = "lock")]
+    #[test]
+    fn shared_locks_for_readers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mcf");
+        MConfig::builder().try_build().unwrap().save(&path).unwrap();
+
+        let reader = MConfigFile::open_read_only(&path).unwrap();
+        assert!(MConfigFile::open_read_only(&path).is_ok());
+        assert!(MConfig::open(&path, None).is_ok());
+        assert_eq!(MConfigFile::open(&path).err(), Some(MCError::Io(std::io::ErrorKind::WouldBlock)));
+        drop(reader);
+
+        assert!(MConfigFile::open(&path).is_ok());
+    }
+
+    #[test]
+    fn directory_path_is_io() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let result = MConfig::builder().try_build().unwrap().save(dir.path());
+
+        assert!(matches!(result, Err(MCError::Io(_))));
+    }
+
+    #[cfg(feature = "lock")]
+    #[test]
+    fn second_lock_fails_while_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mcf");
+        MConfig::builder().try_build().unwrap().save(&path).unwrap();
+
+        let mut held = MConfigFile::open(&path).unwrap();
+        assert_eq!(MConfigFile::open(&path).err(), Some(MCError::Io(std::io::ErrorKind::WouldBlock)));
+        assert_eq!(MConfig::open(&path, None).err(), Some(MCError::Io(std::io::ErrorKind::WouldBlock)));
+
+        let mc = held.load(None).unwrap();
+        held.store(&mc).unwrap();
+        drop(held);
+
+        assert!(MConfigFile::open(&path).is_ok());
+    }
+}
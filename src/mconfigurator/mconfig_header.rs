@@ -0,0 +1,307 @@
+use crate::mconfigurator::{Algorithm, KdfParams, MCError, MConfig, MCResult};
+
+/// The header at the start of serialized data: the fixed fields followed by any optional ones.
+/// Use `MConfig::read_header` to read it without a secret.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub(crate) version: u8,
+    pub(crate) algorithm: Algorithm,
+    pub(crate) flags: u8,
+    pub(crate) fingerprint: Option<[u8; MConfig::FINGERPRINT_LEN]>,
+    pub(crate) kdf: Option<KdfParams>,
+    pub(crate) block_size: Option<usize>,
+    pub(crate) rotate_after: Option<u64>,
+    pub(crate) aead: Option<[u8; MConfig::AEAD_LEN]>,
+    pub(crate) checksum: Option<u32>,
+}
+
+impl Header {
+    /// Parse and check the header of raw serialized data, including its overall length.
+    /// v0 has no algorithm byte; it is always XOR if a secret is used.
+    pub(crate) fn parse(raw: &[u8]) -> MCResult<Header> {
+        let header = Header::parse_fields(raw)?;
+        if raw.len() > header.block_size() {
+            return Err(MCError::TooBig { over_by: raw.len() - header.block_size() }); //maximum length
+        }
+        Ok(header)
+    }
+
+    /// The number of bytes needed to parse the whole header, given the start of the data.
+    /// If `raw` is shorter than the result, read that many bytes and call this again.
+    pub(crate) fn required_len(raw: &[u8]) -> MCResult<usize> {
+        if raw.len() < MConfig::HEADER_SIZE {
+            return Ok(MConfig::HEADER_SIZE);
+        }
+        if raw[0..MConfig::MAGIC_HEADER_BYTES.len()] != MConfig::MAGIC_HEADER_BYTES {
+            return Err(MCError::BadHeader);
+        }
+        let version = raw[MConfig::VERSION_INDEX];
+        if !MConfig::is_supported_version(version) {
+            return Err(MCError::UnknownVersion);
+        }
+        if raw.len() < MConfig::header_size(version) {
+            return Ok(MConfig::header_size(version));
+        }
+        if version == 0 {
+            return Ok(MConfig::HEADER_SIZE);
+        }
+
+        let flags = raw[MConfig::FLAGS_INDEX];
+        let optional = [
+            (MConfig::FINGERPRINT_FLAG, MConfig::FINGERPRINT_LEN),
+            (MConfig::KDF_FLAG, KdfParams::LEN),
+            (MConfig::BLOCK_SIZE_FLAG, MConfig::BLOCK_SIZE_LEN),
+            (MConfig::ROTATE_AFTER_FLAG, MConfig::ROTATE_AFTER_LEN),
+            (MConfig::AEAD_FLAG, MConfig::AEAD_LEN),
+            (MConfig::CHECKSUM_FLAG, MConfig::CHECKSUM_LEN),
+        ];
+        Ok(optional
+            .iter()
+            .filter(|(flag, _)| flags & flag != 0)
+            .fold(MConfig::header_size(version), |acc, (_, len)| acc + len))
+    }
+
+    /// Parse the header fields without checking the overall length.
+    fn parse_fields(raw: &[u8]) -> MCResult<Header> {
+        if raw.len() < MConfig::HEADER_SIZE {
+            return Err(MCError::TooShort); //minimum length
+        }
+
+        //check header magic
+        if raw[0..MConfig::MAGIC_HEADER_BYTES.len()] != MConfig::MAGIC_HEADER_BYTES {
+            return Err(MCError::BadHeader);
+        }
+
+        //check and select version
+        let version = raw[MConfig::VERSION_INDEX];
+        if !MConfig::is_supported_version(version) {
+            return Err(MCError::UnknownVersion);
+        }
+        if version == 0 {
+            return Ok(Header {
+                version,
+                algorithm: Algorithm::Xor,
+                flags: 0,
+                fingerprint: None,
+                kdf: None,
+                block_size: None,
+                rotate_after: None,
+                aead: None,
+                checksum: None,
+            });
+        }
+
+        if raw.len() < MConfig::header_size(version) {
+            return Err(MCError::TooShort);
+        }
+        let algorithm = Algorithm::from_tag(raw[MConfig::ALGORITHM_INDEX])?;
+        let flags = raw[MConfig::FLAGS_INDEX];
+        let known = MConfig::FINGERPRINT_FLAG
+            | MConfig::KDF_FLAG
+            | MConfig::BLOCK_SIZE_FLAG
+            | MConfig::ROTATE_AFTER_FLAG
+            | MConfig::AEAD_FLAG
+            | MConfig::SENTINEL_FLAG
+            | MConfig::CHECKSUM_FLAG;
+        if flags & !known != 0 {
+            return Err(MCError::BadHeader); //unknown flags
+        }
+        if (flags & MConfig::AEAD_FLAG != 0) != (algorithm == Algorithm::ChaCha20Poly1305) {
+            return Err(MCError::BadHeader); //nonce and tag belong to the authenticated cipher only
+        }
+        if algorithm == Algorithm::ChaCha20Poly1305 && flags & MConfig::KDF_FLAG == 0 {
+            return Err(MCError::BadHeader); //the cipher key is always derived from the secret
+        }
+        if (flags & MConfig::SENTINEL_FLAG != 0) != (algorithm == Algorithm::Xor) {
+            return Err(MCError::BadHeader); //XORed entries always start with the sentinel
+        }
+
+        // optional fields follow in flag bit order
+        let mut offset = MConfig::header_size(version);
+        let fingerprint = if flags & MConfig::FINGERPRINT_FLAG != 0 {
+            let mut fingerprint = [0u8; MConfig::FINGERPRINT_LEN];
+            fingerprint.copy_from_slice(Header::field(raw, &mut offset, MConfig::FINGERPRINT_LEN)?);
+            Some(fingerprint)
+        } else {
+            None
+        };
+        let kdf = if flags & MConfig::KDF_FLAG != 0 {
+            Some(KdfParams::from_bytes(Header::field(raw, &mut offset, KdfParams::LEN)?)?)
+        } else {
+            None
+        };
+        let block_size = if flags & MConfig::BLOCK_SIZE_FLAG != 0 {
+            let mut size = [0u8; MConfig::BLOCK_SIZE_LEN];
+            size.copy_from_slice(Header::field(raw, &mut offset, MConfig::BLOCK_SIZE_LEN)?);
+            let size = u32::from_le_bytes(size) as usize;
+            if !MConfig::is_valid_block_size(size) {
+                return Err(MCError::BadHeader);
+            }
+            Some(size)
+        } else {
+            None
+        };
+        let rotate_after = if flags & MConfig::ROTATE_AFTER_FLAG != 0 {
+            let mut ts = [0u8; MConfig::ROTATE_AFTER_LEN];
+            ts.copy_from_slice(Header::field(raw, &mut offset, MConfig::ROTATE_AFTER_LEN)?);
+            Some(u64::from_le_bytes(ts))
+        } else {
+            None
+        };
+        let aead = if flags & MConfig::AEAD_FLAG != 0 {
+            let mut aead = [0u8; MConfig::AEAD_LEN];
+            aead.copy_from_slice(Header::field(raw, &mut offset, MConfig::AEAD_LEN)?);
+            Some(aead)
+        } else {
+            None
+        };
+        let checksum = if flags & MConfig::CHECKSUM_FLAG != 0 {
+            let mut crc = [0u8; MConfig::CHECKSUM_LEN];
+            crc.copy_from_slice(Header::field(raw, &mut offset, MConfig::CHECKSUM_LEN)?);
+            Some(u32::from_le_bytes(crc))
+        } else {
+            None
+        };
+
+        Ok(Header {
+            version,
+            algorithm,
+            flags,
+            fingerprint,
+            kdf,
+            block_size,
+            rotate_after,
+            aead,
+            checksum,
+        })
+    }
+
+    /// Take the next optional field of `len` bytes, advancing `offset` past it.
+    fn field<'a>(raw: &'a [u8], offset: &mut usize, len: usize) -> MCResult<&'a [u8]> {
+        let field = raw.get(*offset..*offset + len).ok_or(MCError::TooShort)?;
+        *offset += len;
+        Ok(field)
+    }
+
+    /// The key the entries are obfuscated with: the secret itself, or derived from it if KDF parameters are set.
+    pub(crate) fn key(&self, secret: Option<&str>) -> Option<Vec<u8>> {
+        secret.map(|secret| match self.kdf {
+            Some(kdf) => kdf.derive(secret),
+            None => secret.as_bytes().to_vec(),
+        })
+    }
+
+    /// Check a key from `Header::key` against the stored fingerprint, if there is one.
+    /// This is cheap compared to deobfuscating and parsing the entries, apart from deriving the key.
+    pub(crate) fn check_key(&self, key: Option<&[u8]>) -> MCResult<()> {
+        match (self.fingerprint, key) {
+            (None, _) => Ok(()),
+            (Some(stored), Some(key)) if stored == MConfig::key_fingerprint(key, Header::fingerprint_salt(&stored)) => {
+                Ok(())
+            }
+            _ => Err(MCError::WrongSecret),
+        }
+    }
+
+    /// The salt at the start of a stored fingerprint.
+    fn fingerprint_salt(fingerprint: &[u8; MConfig::FINGERPRINT_LEN]) -> [u8; MConfig::FINGERPRINT_SALT_LEN] {
+        let mut salt = [0u8; MConfig::FINGERPRINT_SALT_LEN];
+        salt.copy_from_slice(&fingerprint[..MConfig::FINGERPRINT_SALT_LEN]);
+        salt
+    }
+
+    /// The length of this header in bytes, including any optional fields.
+    pub(crate) fn size(&self) -> usize {
+        MConfig::header_size(self.version)
+            + self.fingerprint.map_or(0, |f| f.len())
+            + self.kdf.map_or(0, |_| KdfParams::LEN)
+            + self.block_size.map_or(0, |_| MConfig::BLOCK_SIZE_LEN)
+            + self.rotate_after.map_or(0, |_| MConfig::ROTATE_AFTER_LEN)
+            + self.aead.map_or(0, |_| MConfig::AEAD_LEN)
+            + self.checksum.map_or(0, |_| MConfig::CHECKSUM_LEN)
+    }
+
+    /// The bytes at the start of the block that hold no entries: the header and, if flagged, the sentinel
+    /// at the start of the obfuscated region.
+    pub(crate) fn reserved_len(&self) -> usize {
+        match self.flags & MConfig::SENTINEL_FLAG {
+            0 => self.size(),
+            _ => self.size() + MConfig::SENTINEL_LEN,
+        }
+    }
+
+    /// The number of bytes of each key or value length in the records, see `MConfig::len_prefix_size`.
+    pub(crate) fn len_prefix_size(&self) -> usize {
+        MConfig::len_prefix_size(self.version)
+    }
+
+    /// The format version.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The obfuscation algorithm. v0 data is always XORed if a secret is used.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The raw flags byte, which says which optional fields are present. Always 0 for v0.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// The fingerprint of the obfuscation key, if one is stored: a random salt followed by the tag.
+    pub fn fingerprint(&self) -> Option<[u8; MConfig::FINGERPRINT_LEN]> {
+        self.fingerprint
+    }
+
+    /// The key derivation parameters, if the key is derived from the secret.
+    pub fn kdf(&self) -> Option<KdfParams> {
+        self.kdf
+    }
+
+    /// The length of the whole serialized block in bytes.
+    pub fn block_size(&self) -> usize {
+        self.block_size.unwrap_or(MConfig::MCONFIG_SIZE)
+    }
+
+    /// When the secret should next be rotated, as a Unix timestamp in seconds, if recorded.
+    pub fn rotate_after(&self) -> Option<u64> {
+        self.rotate_after
+    }
+
+    /// The CRC32 of the plaintext entries region, if recorded. Always recorded from version 1 on.
+    pub fn checksum(&self) -> Option<u32> {
+        self.checksum
+    }
+
+    /// Serialize the header.
+    pub(crate) fn to_vec(self) -> Vec<u8> {
+        let mut v: Vec<u8> = Vec::with_capacity(self.size());
+        v.extend_from_slice(&MConfig::MAGIC_HEADER_BYTES);
+        v.push(self.version);
+        if self.version > 0 {
+            v.push(self.algorithm.tag());
+            v.push(self.flags);
+        }
+        if let Some(fingerprint) = self.fingerprint {
+            v.extend_from_slice(&fingerprint);
+        }
+        if let Some(kdf) = self.kdf {
+            v.extend_from_slice(&kdf.to_bytes());
+        }
+        if let Some(block_size) = self.block_size {
+            v.extend_from_slice(&(block_size as u32).to_le_bytes());
+        }
+        if let Some(rotate_after) = self.rotate_after {
+            v.extend_from_slice(&rotate_after.to_le_bytes());
+        }
+        if let Some(aead) = self.aead {
+            v.extend_from_slice(&aead);
+        }
+        if let Some(checksum) = self.checksum {
+            v.extend_from_slice(&checksum.to_le_bytes());
+        }
+        v
+    }
+}
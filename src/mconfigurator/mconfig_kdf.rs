@@ -0,0 +1,70 @@
+use crate::mconfigurator::{MCError, MCResult};
+use sha2::Sha256;
+
+/// Parameters for deriving the obfuscation key from the secret with PBKDF2-HMAC-SHA256.
+/// They are stored in the header, so only the secret is needed to load the data again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    iterations: u32,
+    salt: [u8; KdfParams::SALT_LEN],
+}
+
+impl KdfParams {
+    const SALT_LEN: usize = 16;
+    /// The serialized length: iterations as a little-endian u32, then the salt.
+    pub(crate) const LEN: usize = 4 + KdfParams::SALT_LEN;
+    const KEY_LEN: usize = 32;
+    /// The number of iterations of `KdfParams::default`.
+    pub const DEFAULT_ITERATIONS: u32 = 100_000;
+
+    /// Parameters with the given number of iterations and a new random salt.
+    /// Values below 1 are raised to 1.
+    pub fn new(iterations: u32) -> KdfParams {
+        KdfParams {
+            iterations: iterations.max(1),
+            salt: rand::random(),
+        }
+    }
+
+    /// The number of PBKDF2 iterations.
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    /// Derive the obfuscation key from the secret.
+    pub(crate) fn derive(&self, secret: &str) -> Vec<u8> {
+        let mut key = vec![0u8; KdfParams::KEY_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha256>(secret.as_bytes(), &self.salt, self.iterations, &mut key);
+        key
+    }
+
+    /// Parse the serialized parameters. Zero iterations are rejected as a bad header.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> MCResult<KdfParams> {
+        let mut iterations = [0u8; 4];
+        iterations.copy_from_slice(&bytes[..4]);
+        let iterations = u32::from_le_bytes(iterations);
+        if iterations == 0 {
+            return Err(MCError::BadHeader);
+        }
+
+        let mut salt = [0u8; KdfParams::SALT_LEN];
+        salt.copy_from_slice(&bytes[4..KdfParams::LEN]);
+        Ok(KdfParams { iterations, salt })
+    }
+
+    /// Serialize the parameters for the header.
+    pub(crate) fn to_bytes(self) -> [u8; KdfParams::LEN] {
+        let mut bytes = [0u8; KdfParams::LEN];
+        bytes[..4].copy_from_slice(&self.iterations.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.salt);
+        bytes
+    }
+}
+
+impl Default for KdfParams {
+    /// Parameters with `DEFAULT_ITERATIONS` and a new random salt.
+    /// `Algorithm::ChaCha20Poly1305` uses these when no others are set.
+    fn default() -> KdfParams {
+        KdfParams::new(KdfParams::DEFAULT_ITERATIONS)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::mconfigurator::mconfig_header::Header;
+use crate::mconfigurator::{MCError, MConfig, MCResult};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+impl MConfig {
+    /// Serialize into a vector like `to_vec` and also return an HMAC-SHA256 tag over the plaintext,
+    /// keyed with the secret (or an empty key if there is none).
+    /// The tag is meant to be stored apart from the data, e.g. in an audit log, and checked with
+    /// `MConfigBuilder::try_build_detached`. Fails like `try_to_vec` if the contents cannot be serialized.
+    pub fn to_vec_detached(&self) -> MCResult<(Vec<u8>, [u8; 32])> {
+        self.validate()?;
+
+        let mut header = self.header();
+        let plain = self.entries_to_vec();
+        let key = header.key(self.secret.as_deref());
+        let mut obfuscated = MConfig::obfuscate(plain.clone(), key.as_deref(), &mut header);
+        let header_bytes = header.to_vec();
+        let mac = MConfig::plaintext_mac(&header_bytes, &plain, &self.secret).finalize().into_bytes();
+
+        let mut v: Vec<u8> = Vec::with_capacity(self.block_size);
+        v.extend_from_slice(&header_bytes);
+        v.append(&mut obfuscated);
+        assert_eq!(v.len(), self.block_size);
+        Ok((v, mac.into()))
+    }
+
+    /// Check a detached tag against serialized data, deobfuscating it with the secret first.
+    pub(crate) fn verify_detached(raw: &[u8], secret: &Option<String>, mac: &[u8; 32]) -> MCResult<()> {
+        let header = Header::parse(raw)?;
+        let (header_bytes, region) = raw.split_at(header.size());
+        let key = header.key(secret.as_deref());
+        let plain = MConfig::deobfuscate(region.to_vec(), key.as_deref(), &header)
+            .map_err(|_| MCError::IntegrityFailure)?;
+
+        MConfig::plaintext_mac(header_bytes, &plain, secret)
+            .verify_slice(mac)
+            .map_err(|_| MCError::IntegrityFailure)
+    }
+
+    /// The salt followed by a short tag derived from the obfuscation key and the salt, stored in the header
+    /// to reject a wrong secret early. The key comes from `Header::key`, so with KDF parameters checking a
+    /// guess costs a full key derivation, and the salt keeps equal secrets from matching across files.
+    pub(crate) fn key_fingerprint(key: &[u8], salt: [u8; MConfig::FINGERPRINT_SALT_LEN]) -> [u8; MConfig::FINGERPRINT_LEN] {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(b"MConfig secret fingerprint");
+        mac.update(&salt);
+        let mut fingerprint = [0u8; MConfig::FINGERPRINT_LEN];
+        fingerprint[..MConfig::FINGERPRINT_SALT_LEN].copy_from_slice(&salt);
+        fingerprint[MConfig::FINGERPRINT_SALT_LEN..]
+            .copy_from_slice(&mac.finalize().into_bytes()[..MConfig::FINGERPRINT_LEN - MConfig::FINGERPRINT_SALT_LEN]);
+        fingerprint
+    }
+
+    /// The sentinel at the start of XORed entries. It is derived from the key, since a fixed plaintext would
+    /// give away the leading key bytes.
+    pub(crate) fn entries_sentinel(key: &[u8]) -> [u8; MConfig::SENTINEL_LEN] {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(b"MConfig entries sentinel");
+        let mut sentinel = [0u8; MConfig::SENTINEL_LEN];
+        sentinel.copy_from_slice(&mac.finalize().into_bytes()[..MConfig::SENTINEL_LEN]);
+        sentinel
+    }
+
+    fn plaintext_mac(header: &[u8], entries: &[u8], secret: &Option<String>) -> HmacSha256 {
+        let key = secret.as_deref().unwrap_or("").as_bytes();
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(header);
+        mac.update(entries);
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn detached_mac_round_trip() {
+        let mut mc = MConfig::builder().secret("audit").kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Key".to_string(), Some("Value".to_string())).unwrap();
+
+        let (bytes, mac) = mc.to_vec_detached().unwrap();
+        let loaded = MConfig::builder()
+            .secret("audit")
+            .load(bytes)
+            .try_build_detached(&mac)
+            .unwrap();
+
+        assert_eq!(loaded.get("Key"), Some(&Some("Value".to_string())));
+    }
+
+    #[test]
+    fn detached_mac_mismatch_fails() {
+        let mut mc = MConfig::builder().secret("audit").kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Key".to_string(), Some("Value".to_string())).unwrap();
+
+        let (bytes, mut mac) = mc.to_vec_detached().unwrap();
+        mac[0] ^= 1;
+        let result = MConfig::builder()
+            .secret("audit")
+            .load(bytes)
+            .try_build_detached(&mac);
+
+        assert_eq!(result.err(), Some(MCError::IntegrityFailure));
+    }
+
+    #[test]
+    fn detached_mac_checks_contents() {
+        let mut mc = MConfig::builder().secret("audit").try_build().unwrap();
+        mc.set_secret(Some(String::new()));
+        assert_eq!(mc.to_vec_detached().err(), Some(MCError::EmptySecret));
+
+        // contents edited past the limits fail instead of panicking
+        mc.set_secret(Some("audit".to_string()));
+        mc.set_version(2).unwrap();
+        mc.try_insert("Key".to_string(), Some("v".repeat(8_000))).unwrap();
+        mc.try_insert("Other".to_string(), None).unwrap();
+        *mc.get_mut("Other").unwrap() = Some("v".repeat(1_000));
+        assert!(matches!(mc.to_vec_detached(), Err(MCError::TooBig { .. })));
+    }
+}
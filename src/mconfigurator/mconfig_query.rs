@@ -0,0 +1,136 @@
+use crate::mconfigurator::{MCError, MConfig, MCResult};
+
+impl MConfig {
+    /// Parse a URL query string such as `a=1&b=2&c` into a new MConfig of the default version.
+    /// Keys and values are percent-decoded and `+` is read as a space. A key without `=` or with an empty value,
+    /// as in `c=`, becomes a valueless entry, since an empty value is stored the same way and would load as one.
+    /// A key that appears more than once keeps its last value.
+    /// Fails with `InvalidFormat` on a malformed escape, `InvalidUTF8` if the decoded bytes are not UTF-8,
+    /// or as `try_insert` does if an entry does not fit.
+    pub fn from_query(s: &str, secret: Option<&str>) -> MCResult<MConfig> {
+        let mut mc = MConfig::builder().optional_secret(secret).try_build()?;
+
+        for pair in s.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = match pair.split_once('=') {
+                Some((k, v)) if !v.is_empty() => (k, Some(v)),
+                Some((k, _)) => (k, None),
+                None => (pair, None),
+            };
+            let key = MConfig::percent_decode(key)?;
+            let value = value.map(MConfig::percent_decode).transpose()?;
+            mc.try_insert(key, value)?;
+        }
+
+        Ok(mc)
+    }
+
+    /// Write the entries as a URL query string sorted by key, the reverse of `from_query`.
+    /// Keys and values are percent-encoded and valueless entries are written as bare keys.
+    pub fn to_query(&self) -> String {
+        self.to_sorted_vec()
+            .iter()
+            .map(|(k, v)| match v {
+                Some(v) => format!("{}={}", MConfig::percent_encode(k), MConfig::percent_encode(v)),
+                None => MConfig::percent_encode(k),
+            })
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+
+    /// Decode `%XX` escapes, and `+` as a space, as in form-encoded query strings.
+    fn percent_decode(s: &str) -> MCResult<String> {
+        let bytes = s.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'%' => {
+                    let hex = bytes.get(i + 1..i + 3).ok_or(MCError::InvalidFormat)?;
+                    let hex = std::str::from_utf8(hex).map_err(|_| MCError::InvalidFormat)?;
+                    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        return Err(MCError::InvalidFormat);
+                    }
+                    decoded.push(u8::from_str_radix(hex, 16).map_err(|_| MCError::InvalidFormat)?);
+                    i += 3;
+                }
+                b'+' => {
+                    decoded.push(b' ');
+                    i += 1;
+                }
+                b => {
+                    decoded.push(b);
+                    i += 1;
+                }
+            }
+        }
+
+        String::from_utf8(decoded).map_err(|_| MCError::InvalidUTF8)
+    }
+
+    /// Escape everything except the RFC 3986 unreserved characters.
+    fn percent_encode(s: &str) -> String {
+        let mut encoded = String::with_capacity(s.len());
+        for b in s.bytes() {
+            if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+                encoded.push(b as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", b));
+            }
+        }
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn from_query_decodes() {
+        let mc = MConfig::from_query("greeting=hello%20world%21&name=J%C3%BCrgen+S&flag&empty=", None).unwrap();
+
+        assert_eq!(
+            mc.to_sorted_vec(),
+            vec![
+                ("empty".to_string(), None),
+                ("flag".to_string(), None),
+                ("greeting".to_string(), Some("hello world!".to_string())),
+                ("name".to_string(), Some("Jürgen S".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_query_bad_escape_fails() {
+        assert_eq!(MConfig::from_query("a=%2", None).err(), Some(MCError::InvalidFormat));
+        assert_eq!(MConfig::from_query("a=%zz", None).err(), Some(MCError::InvalidFormat));
+        assert_eq!(MConfig::from_query("a=%+1", None).err(), Some(MCError::InvalidFormat));
+        assert_eq!(MConfig::from_query("a=%ff", None).err(), Some(MCError::InvalidUTF8));
+    }
+
+    #[test]
+    fn to_query_round_trip() {
+        let mut mc = MConfig::from_str_pairs(&[("b key", "1+1=2"), ("a", "x&y"), ("ü", "100%")], None).unwrap();
+        mc.try_insert("flag".to_string(), None).unwrap();
+
+        let query = mc.to_query();
+        assert_eq!(query, "a=x%26y&b%20key=1%2B1%3D2&flag&%C3%BC=100%25");
+        assert_eq!(MConfig::from_query(&query, None).unwrap().to_sorted_vec(), mc.to_sorted_vec());
+    }
+
+    #[test]
+    fn from_query_empty_value_matches_saved() {
+        let mut mc = MConfig::from_query("k=&j", Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        assert_eq!(mc["k"], None);
+        assert_eq!(mc["j"], None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("query.mcf");
+        mc.save(&path).unwrap();
+        let reloaded = MConfig::open(&path, Some("TACOS")).unwrap();
+        assert_eq!(reloaded.to_sorted_vec(), mc.to_sorted_vec());
+        assert_eq!(reloaded.to_query(), "j&k");
+    }
+}
@@ -0,0 +1,74 @@
+use crate::mconfigurator::{MConfig, MConfigIter};
+
+/// An owned MConfig that can only be read.
+/// Unlike `&MConfig` it can be moved and stored, while still exposing no way to change the contents:
+///
+/// ```compile_fail
+/// use mconfig::MConfig;
+///
+/// let mut ro = MConfig::builder().try_build().unwrap().into_readonly();
+/// ro.try_insert("Key".to_string(), None);
+/// ```
+pub struct ReadOnlyMConfig {
+    inner: MConfig,
+}
+
+impl ReadOnlyMConfig {
+    /// Retrieve the value at key. Returns None if the key is not set.
+    pub fn get(&self, key: &str) -> Option<&Option<String>> {
+        self.inner.get(key)
+    }
+
+    /// Check if a given key is present.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    /// Return the number of entries.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check if there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Iterate over the entries.
+    pub fn iter(&self) -> MConfigIter<'_> {
+        self.inner.iter()
+    }
+
+    /// Return a `Vec<u8>` of the MConfig, see `MConfig::to_vec`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.inner.to_vec()
+    }
+}
+
+impl MConfig {
+    /// Wrap this MConfig so that it can be handed to code that must not modify it.
+    pub fn into_readonly(self) -> ReadOnlyMConfig {
+        ReadOnlyMConfig { inner: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn readonly_reads() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        let ro = mc.into_readonly();
+
+        assert_eq!(ro.get("Hello"), Some(&Some("World".to_string())));
+        assert!(ro.contains_key("Hello"));
+        assert_eq!(ro.len(), 1);
+        assert!(!ro.is_empty());
+        assert_eq!(ro.iter().count(), 1);
+
+        let loaded = MConfig::builder().secret("TACOS").load(ro.to_vec()).try_build().unwrap();
+        assert_eq!(loaded.get("Hello"), Some(&Some("World".to_string())));
+    }
+}
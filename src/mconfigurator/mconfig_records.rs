@@ -0,0 +1,87 @@
+use crate::mconfigurator::{MCError, MCResult};
+
+/// A raw key and optional value as stored in a record.
+pub(crate) type Record<'a> = (&'a [u8], Option<&'a [u8]>);
+
+/// Read a little-endian length of `prefix` bytes at `pos`, or None if the buffer ends first.
+pub(crate) fn read_len(buffer: &[u8], pos: usize, prefix: usize) -> Option<usize> {
+    let bytes = buffer.get(pos..pos + prefix)?;
+    Some(bytes.iter().rev().fold(0, |acc, b| acc << 8 | *b as usize))
+}
+
+/// Append `len` as a little-endian length of `prefix` bytes.
+pub(crate) fn push_len(v: &mut Vec<u8>, len: usize, prefix: usize) {
+    assert!(len >> (8 * prefix) == 0, "length {len} does not fit in {prefix} bytes");
+    v.extend_from_slice(&len.to_le_bytes()[..prefix]);
+}
+
+/// Iterator over the raw records of a deobfuscated entries region, with lengths of `prefix` bytes.
+/// Yields `(key, value)` byte slices until the terminator (or the end of the buffer) is reached.
+/// After an error is yielded the iterator is exhausted.
+pub(crate) struct Records<'a> {
+    buffer: &'a [u8],
+    prefix: usize,
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> Records<'a> {
+    pub(crate) fn new(buffer: &'a [u8], prefix: usize) -> Records<'a> {
+        Records {
+            buffer,
+            prefix,
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// Read the record at the current position and advance past it.
+    fn read_record(&mut self) -> Option<MCResult<Record<'a>>> {
+        //key length zero means end of data/start of padding
+        let key_len = read_len(self.buffer, self.pos, self.prefix)?;
+        if key_len == 0 {
+            return None;
+        }
+        self.pos += self.prefix;
+
+        let key = match self.buffer.get(self.pos..self.pos + key_len) {
+            Some(k) => k,
+            None => return Some(Err(MCError::TruncatedKey)),
+        };
+        self.pos += key_len;
+
+        let val_len = match read_len(self.buffer, self.pos, self.prefix) {
+            Some(v) => v,
+            None => return Some(Err(MCError::MissingKey)),
+        };
+        self.pos += self.prefix;
+
+        if val_len == 0 {
+            return Some(Ok((key, None))); //valueless keys are allowed
+        }
+
+        let value = match self.buffer.get(self.pos..self.pos + val_len) {
+            Some(v) => v,
+            None => return Some(Err(MCError::TruncatedValue)),
+        };
+        self.pos += val_len;
+
+        Some(Ok((key, Some(value))))
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = MCResult<Record<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.read_record();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+}
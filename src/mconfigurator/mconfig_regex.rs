@@ -0,0 +1,97 @@
+use crate::mconfigurator::{MCError, MCHashMap, MConfig, MCResult};
+use regex::Regex;
+
+impl MConfig {
+    /// Remove every key matching the regular expression `pattern`, e.g. `^temp\.` to drop a namespace.
+    /// Returns the number of keys removed. Fails with `InvalidFormat` if the pattern does not compile.
+    pub fn remove_matching(&mut self, pattern: &str) -> MCResult<usize> {
+        let re = Regex::new(pattern).map_err(|_| MCError::InvalidFormat)?;
+
+        let before = self.entries.len();
+        self.entries.retain(|k, _| !re.is_match(k));
+        Ok(before - self.entries.len())
+    }
+
+    /// Rename every key matching the regular expression `pattern` by replacing the first match with
+    /// `replacement`, which may refer to capture groups, e.g. `old\.(.*)` to `new.$1`.
+    /// Returns the number of keys renamed. Nothing is changed if this fails: with `InvalidFormat` if
+    /// the pattern does not compile, `DuplicateKey` if two keys would end up with the same name,
+    /// or `EmptyKey`/`KeyTooBig`/`TooBig` if the new names are empty or do not fit.
+    pub fn rename_matching(&mut self, pattern: &str, replacement: &str) -> MCResult<usize> {
+        let re = Regex::new(pattern).map_err(|_| MCError::InvalidFormat)?;
+
+        let mut renamed = MCHashMap::with_capacity(self.entries.len());
+        let mut count = 0;
+        for (k, v) in &self.entries {
+            let new_key = if re.is_match(k) {
+                count += 1;
+                re.replace(k, replacement).into_owned()
+            } else {
+                k.clone()
+            };
+
+            self.check_entry(&new_key, v)?;
+            if renamed.insert(new_key, v.clone()).is_some() {
+                return Err(MCError::DuplicateKey);
+            }
+        }
+        let used = MConfig::used_len_for(&renamed, &self.header(), None);
+        if used > self.block_size {
+            return Err(MCError::TooBig { over_by: used - self.block_size });
+        }
+
+        self.entries = renamed;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn remove_matching_prefix() {
+        let mut mc = MConfig::from_str_pairs(
+            &[("temp.a", "1"), ("temp.b", "2"), ("db.host", "localhost"), ("attempt.temp", "3")],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(mc.remove_matching(r"^temp\."), Ok(2));
+        assert_eq!(mc.len(), 2);
+        assert!(mc.contains_key("db.host"));
+        assert!(mc.contains_key("attempt.temp"));
+    }
+
+    #[test]
+    fn rename_matching_namespace() {
+        let mut mc = MConfig::from_str_pairs(&[("old.host", "a"), ("old.port", "1"), ("other", "x")], None).unwrap();
+
+        assert_eq!(mc.rename_matching(r"^old\.(.*)", "new.$1"), Ok(2));
+        assert_eq!(
+            mc.to_sorted_vec(),
+            vec![
+                ("new.host".to_string(), Some("a".to_string())),
+                ("new.port".to_string(), Some("1".to_string())),
+                ("other".to_string(), Some("x".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_matching_collision_rolls_back() {
+        let mut mc = MConfig::from_str_pairs(&[("old.host", "a"), ("new.host", "b"), ("old.port", "1")], None).unwrap();
+        let before = mc.to_sorted_vec();
+
+        assert_eq!(mc.rename_matching(r"^old\.(.*)", "new.$1"), Err(MCError::DuplicateKey));
+        assert_eq!(mc.to_sorted_vec(), before);
+    }
+
+    #[test]
+    fn remove_matching_bad_pattern() {
+        let mut mc = MConfig::from_str_pairs(&[("temp.a", "1")], None).unwrap();
+
+        assert_eq!(mc.remove_matching("(unclosed"), Err(MCError::InvalidFormat));
+        assert_eq!(mc.len(), 1);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::mconfigurator::{MCHashMap, MConfig};
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::BTreeMap;
+
+/// Serializes the entries as a flat map of keys to nullable strings, sorted by key.
+/// The secret and the header settings are not part of the output.
+impl Serialize for MConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let sorted: BTreeMap<&String, &Option<String>> = self.entries.iter().collect();
+        let mut map = serializer.serialize_map(Some(sorted.len()))?;
+        for (k, v) in sorted {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+/// Deserializes a flat map of keys to nullable strings into a new MConfig of the default version
+/// without a secret. Fails if an entry does not fit, as `try_insert` does.
+impl<'de> Deserialize<'de> for MConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MConfig, D::Error> {
+        let entries = MCHashMap::deserialize(deserializer)?;
+
+        let mut mc = MConfig::builder().try_build().map_err(D::Error::custom)?;
+        for (k, v) in entries {
+            mc.try_insert(k, v).map_err(D::Error::custom)?;
+        }
+        Ok(mc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn serde_json_round_trip() {
+        let mut mc = MConfig::from_str_pairs(&[("b", "2"), ("a", "1")], Some("TACOS")).unwrap();
+        mc.try_insert("c".to_string(), None).unwrap();
+
+        let json = serde_json::to_string(&mc).unwrap();
+        assert_eq!(json, r#"{"a":"1","b":"2","c":null}"#);
+        assert!(!json.contains("TACOS"));
+
+        let back: MConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.to_sorted_vec(), mc.to_sorted_vec());
+    }
+
+    #[test]
+    fn serde_rejects_oversized_entries() {
+        let json = format!(r#"{{"{}":null}}"#, "k".repeat(256));
+        let err = serde_json::from_str::<MConfig>(&json).err().unwrap();
+        assert!(err.to_string().contains("KeyTooBig"));
+
+        assert!(serde_json::from_str::<MConfig>(r#"{"a":1}"#).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use crate::mconfigurator::mconfig_records::Records;
+use crate::mconfigurator::{MCError, MConfig, MCResult};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+impl MConfig {
+    /// Sign the entries with an Ed25519 key, so that anyone with the verifying key can check where they came from.
+    /// The signature covers `canonical_bytes`, so it does not depend on the secret, version or other header settings.
+    pub fn sign(&self, signing_key: &SigningKey) -> [u8; 64] {
+        signing_key.sign(&self.canonical_bytes()).to_bytes()
+    }
+
+    /// Check a signature from `sign` against `canonical_bytes` output, e.g. of a config loaded with or without
+    /// its secret. Returns `Ok(false)` if the signature does not match the entries or the key.
+    /// Fails with a record error or `InvalidFormat` if `bytes` are not whole records followed by the terminator.
+    pub fn verify_signature(bytes: &[u8], signature: &[u8; 64], verifying_key: &VerifyingKey) -> MCResult<bool> {
+        let prefix = MConfig::WIDE_LEN_PREFIX;
+        for record in Records::new(bytes, prefix) {
+            record?;
+        }
+        if !bytes.ends_with(&[0; MConfig::WIDE_LEN_PREFIX]) || MConfig::records(bytes, prefix).len() != bytes.len() {
+            return Err(MCError::InvalidFormat);
+        }
+
+        Ok(verifying_key.verify(bytes, &Signature::from_bytes(signature)).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn sign_and_verify_round_trip() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        let signature = mc.sign(&signing_key);
+
+        // the receiver checks the entries it loaded, whatever the secret or layout of its copy
+        let loaded = MConfig::load_verified(&mc.to_vec(), "TACOS").unwrap();
+        let verifying_key = signing_key.verifying_key();
+        assert_eq!(MConfig::verify_signature(&loaded.canonical_bytes(), &signature, &verifying_key), Ok(true));
+
+        let other_key = SigningKey::from_bytes(&[8u8; 32]).verifying_key();
+        assert_eq!(MConfig::verify_signature(&loaded.canonical_bytes(), &signature, &other_key), Ok(false));
+    }
+
+    #[test]
+    fn tampered_entries_fail_to_verify() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap();
+        let signature = mc.sign(&signing_key);
+
+        mc.insert_str("Hello", Some("world")).unwrap();
+        assert_eq!(MConfig::verify_signature(&mc.canonical_bytes(), &signature, &verifying_key), Ok(false));
+
+        let mut flipped = signature;
+        flipped[0] ^= 1;
+        mc.insert_str("Hello", Some("World")).unwrap();
+        assert_eq!(MConfig::verify_signature(&mc.canonical_bytes(), &flipped, &verifying_key), Ok(false));
+        assert_eq!(MConfig::verify_signature(&mc.canonical_bytes(), &signature, &verifying_key), Ok(true));
+
+        let mut truncated = mc.canonical_bytes();
+        truncated.pop();
+        assert_eq!(
+            MConfig::verify_signature(&truncated, &signature, &verifying_key),
+            Err(MCError::InvalidFormat)
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::mconfigurator::mconfig_header::Header;
+use crate::mconfigurator::mconfig_records::Records;
+use crate::mconfigurator::{MCError, MConfig, MCResult};
+use std::borrow::Cow;
+
+/// Read-only view over serialized MConfig data.
+/// Unlike a full load, no map is built up front; every lookup scans the records instead.
+/// This suits reading a few keys from many blocks. The data is only copied if it has to be deobfuscated.
+pub struct MConfigView<'a> {
+    entries: Cow<'a, [u8]>,
+    prefix: usize,
+}
+
+impl<'a> MConfigView<'a> {
+    /// Check the header of `raw` and deobfuscate the entries if a secret is given.
+    /// Fails with `EmptySecret` for `Some("")`, as a full load does.
+    pub fn new(raw: &'a [u8], secret: Option<&str>) -> MCResult<MConfigView<'a>> {
+        if secret == Some("") {
+            return Err(MCError::EmptySecret);
+        }
+        let header = Header::parse(raw)?;
+        let key = header.key(secret);
+        header.check_key(key.as_deref())?;
+        let region = &raw[header.size()..];
+
+        let entries = match key {
+            Some(key) => Cow::Owned(MConfig::deobfuscate(region.to_vec(), Some(&key), &header)?),
+            None => Cow::Borrowed(region),
+        };
+
+        Ok(MConfigView { entries, prefix: header.len_prefix_size() })
+    }
+
+    /// Retrieve the value at key. Returns None if the key is not present, has no value, or
+    /// the value is not valid UTF-8. Use `contains_key` to tell valueless keys apart.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.find(key)
+            .ok()
+            .flatten()
+            .flatten()
+            .and_then(|v| std::str::from_utf8(v).ok())
+    }
+
+    /// Check if a given key is present.
+    pub fn contains_key(&self, key: &str) -> bool {
+        matches!(self.find(key), Ok(Some(_)))
+    }
+
+    /// Scan the records for key, stopping at the first match or malformed record.
+    fn find(&self, key: &str) -> MCResult<Option<Option<&[u8]>>> {
+        for record in Records::new(&self.entries, self.prefix) {
+            let (k, v) = record?;
+            if k == key.as_bytes() {
+                return Ok(Some(v));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl MConfig {
+    /// Check whether serialized data contains a key without parsing it into an MConfig.
+    /// The scan stops at the first match; a malformed record before that is an error.
+    pub fn blob_contains_key(bytes: &[u8], secret: Option<&str>, key: &str) -> MCResult<bool> {
+        MConfigView::new(bytes, secret)?.find(key).map(|v| v.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mconfigurator::*;
+
+    #[test]
+    fn view_matches_full_parse() {
+        let mut mc = MConfig::builder().secret("TACOS").kdf(KdfParams::new(16)).try_build().unwrap();
+        mc.try_insert("Hello".to_string(), Some("World".to_string())).unwrap();
+        mc.try_insert("Taco".to_string(), Some("Tuesday".to_string())).unwrap();
+        mc.try_insert("Bye".to_string(), None).unwrap();
+        let raw = mc.to_vec();
+
+        let full = MConfig::builder().load(raw.clone()).secret("TACOS").try_build().unwrap();
+        let view = MConfigView::new(&raw, Some("TACOS")).unwrap();
+
+        for key in ["Hello", "Taco"] {
+            assert_eq!(view.get(key), full.get(key).unwrap().as_deref());
+        }
+        assert!(view.contains_key("Bye"));
+        assert_eq!(view.get("Bye"), None);
+        assert!(!view.contains_key("Missing"));
+    }
+
+    #[test]
+    fn blob_contains_key_scan() {
+        let mut mc = MConfig::from_str_pairs(&[("Hello", "World"), ("Taco", "Tuesday")], Some("TACOS")).unwrap();
+        mc.rehash(KdfParams::new(16));
+        let raw = mc.to_vec();
+
+        assert_eq!(MConfig::blob_contains_key(&raw, Some("TACOS"), "Taco"), Ok(true));
+        assert_eq!(MConfig::blob_contains_key(&raw, Some("TACOS"), "World"), Ok(false));
+        assert_eq!(MConfig::blob_contains_key(&raw[..3], Some("TACOS"), "Taco"), Err(MCError::TooShort));
+    }
+
+    #[test]
+    fn view_borrows_without_secret() {
+        let raw = MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap().to_vec();
+        let view = MConfigView::new(&raw, None).unwrap();
+
+        assert_eq!(view.get("Hello"), Some("World"));
+    }
+}
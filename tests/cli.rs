@@ -0,0 +1,214 @@
+use mconfig::MConfig;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mconfig_demo"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn empty_secret_line_opens_plain_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("plain.mcf");
+    MConfig::from_str_pairs(&[("Hello", "World")], None).unwrap().save(&path).unwrap();
+
+    let output = run(&["-f", path.to_str().unwrap(), "-l"], "\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello: World"));
+}
+
+#[test]
+fn create_writes_a_loadable_file_and_refuses_to_overwrite() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("new.mcf");
+    let file = path.to_str().unwrap();
+
+    let output = run(&["-f", file, "-c", "-k", "Hello", "-v", "World"], "TACOS\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let loaded = MConfig::load_from_file(&path, Some("TACOS")).unwrap();
+    assert_eq!(loaded["Hello"], Some("World".to_string()));
+
+    let output = run(&["-f", file, "-c"], "TACOS\n");
+    assert!(!output.status.success());
+    assert!(MConfig::load_from_file(&path, Some("TACOS")).unwrap().contains_key("Hello"));
+
+    let output = run(&["-f", file, "-c", "--force"], "BURRITOS\n");
+    assert!(output.status.success());
+    assert!(MConfig::load_from_file(&path, Some("BURRITOS")).unwrap().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn read_only_file_can_be_listed() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("readonly.mcf");
+    MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap().save(&path).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o400)).unwrap();
+
+    let output = run(&["-f", path.to_str().unwrap(), "-l"], "TACOS\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello: World"));
+}
+
+#[test]
+fn batch_applies_all_operations() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("batch.mcf");
+    MConfig::from_str_pairs(&[("old", "value"), ("keep", "me")], None).unwrap().save(&path).unwrap();
+    let batch = dir.path().join("ops.txt");
+    std::fs::write(&batch, "# migrate\nset host example.com\nset greeting hello world\n\nempty flag\nremove old\n").unwrap();
+
+    let output = run(&["-f", path.to_str().unwrap(), "--batch", batch.to_str().unwrap()], "\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Applied 4 operations"));
+
+    let mc = MConfig::open(&path, None).unwrap();
+    assert_eq!(
+        mc.to_sorted_vec(),
+        vec![
+            ("flag".to_string(), None),
+            ("greeting".to_string(), Some("hello world".to_string())),
+            ("host".to_string(), Some("example.com".to_string())),
+            ("keep".to_string(), Some("me".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn batch_failure_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("batch.mcf");
+    MConfig::from_str_pairs(&[("keep", "me")], None).unwrap().save(&path).unwrap();
+    let before = std::fs::read(&path).unwrap();
+    let batch = dir.path().join("ops.txt");
+    let too_long = "v".repeat(300);
+    std::fs::write(&batch, format!("set host example.com\nremove keep\nset big {too_long}\n")).unwrap();
+
+    let output = run(&["-f", path.to_str().unwrap(), "--batch", batch.to_str().unwrap()], "\n");
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+}
+
+#[test]
+fn import_json_with_null_and_repeated_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("import.mcf");
+    MConfig::from_str_pairs(&[("keep", "me")], None).unwrap().save(&path).unwrap();
+    let import = dir.path().join("entries.json");
+    std::fs::write(&import, r#"{"host": "example.com", "flag": null, "host": "example.org"}"#).unwrap();
+
+    let output = run(&["-f", path.to_str().unwrap(), "--import", import.to_str().unwrap()], "\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Imported 2 entries"));
+
+    let mc = MConfig::open(&path, None).unwrap();
+    assert_eq!(
+        mc.to_sorted_vec(),
+        vec![
+            ("flag".to_string(), None),
+            ("host".to_string(), Some("example.org".to_string())),
+            ("keep".to_string(), Some("me".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn import_env_with_quotes_bare_keys_and_export() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("import.mcf");
+    MConfig::from_str_pairs(&[], None).unwrap().save(&path).unwrap();
+    let import = dir.path().join("entries.env");
+    std::fs::write(&import, "# settings\nexport HOST=example.com\nGREETING=\"hello world\"\nNAME='single'\n\nFLAG\n").unwrap();
+
+    let output = run(&["-f", path.to_str().unwrap(), "--import", import.to_str().unwrap()], "\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Imported 4 entries"));
+
+    let mc = MConfig::open(&path, None).unwrap();
+    assert_eq!(
+        mc.to_sorted_vec(),
+        vec![
+            ("FLAG".to_string(), None),
+            ("GREETING".to_string(), Some("hello world".to_string())),
+            ("HOST".to_string(), Some("example.com".to_string())),
+            ("NAME".to_string(), Some("single".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn import_failure_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("import.mcf");
+    MConfig::from_str_pairs(&[("keep", "me")], None).unwrap().save(&path).unwrap();
+    let before = std::fs::read(&path).unwrap();
+    let import = dir.path().join("entries.env");
+    std::fs::write(&import, format!("BIG={}\nHOST=example.com\n", "v".repeat(300))).unwrap();
+
+    let output = run(&["-f", path.to_str().unwrap(), "--import", import.to_str().unwrap()], "\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("BIG"));
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+}
+
+#[test]
+fn export_round_trips_through_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source.mcf");
+    let mut mc = MConfig::from_str_pairs(&[("host", "example.com"), ("greeting", "hello world")], None).unwrap();
+    mc.try_insert("flag", None).unwrap();
+    mc.save(&source).unwrap();
+    let export = dir.path().join("export.json");
+
+    let output = run(&["-f", source.to_str().unwrap(), "--export", export.to_str().unwrap()], "\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Exported 3 entries"));
+
+    let target = dir.path().join("target.mcf");
+    MConfig::from_str_pairs(&[], None).unwrap().save(&target).unwrap();
+    let output = run(&["-f", target.to_str().unwrap(), "--import", export.to_str().unwrap()], "\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(MConfig::open(&target, None).unwrap().to_sorted_vec(), mc.to_sorted_vec());
+}
+
+#[test]
+fn export_of_empty_store_is_empty_object() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("empty.mcf");
+    MConfig::from_str_pairs(&[], None).unwrap().save(&path).unwrap();
+    let export = dir.path().join("export.json");
+
+    let output = run(&["-f", path.to_str().unwrap(), "--export", export.to_str().unwrap()], "\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read_to_string(&export).unwrap(), "{}");
+}
+
+#[test]
+fn export_conflicts_with_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("conflict.mcf");
+    MConfig::from_str_pairs(&[("host", "example.com")], None).unwrap().save(&path).unwrap();
+    let export = dir.path().join("export.json");
+    let file = path.to_str().unwrap();
+
+    for flag in ["--export", "--list"] {
+        let mut args = vec!["-f", file, "-k", "host", flag];
+        if flag == "--export" {
+            args.push(export.to_str().unwrap());
+        }
+        let output = run(&args, "\n");
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+    }
+    assert!(!export.exists());
+}
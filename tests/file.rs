@@ -0,0 +1,22 @@
+use mconfig::mconfigurator::MCError;
+use mconfig::MConfig;
+
+#[test]
+fn save_to_file_and_reload_with_secret() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.mcf");
+
+    let mut mc = MConfig::from_str_pairs(&[("Hello", "World")], Some("TACOS")).unwrap();
+    mc.try_insert("Bye", None).unwrap();
+    mc.save_to_file(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 8_192);
+
+    let loaded = MConfig::load_from_file(&path, Some("TACOS")).unwrap();
+    assert_eq!(loaded.to_sorted_vec(), mc.to_sorted_vec());
+
+    assert!(MConfig::load_from_file(&path, Some("tacos")).is_err());
+    assert!(matches!(
+        MConfig::load_from_file(dir.path().join("missing.mcf"), Some("TACOS")),
+        Err(MCError::Io(std::io::ErrorKind::NotFound))
+    ));
+}